@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+DROP INDEX IF EXISTS articles_creation_date_id_idx;
+DROP INDEX IF EXISTS articles_user_id_creation_date_idx;
+DROP INDEX IF EXISTS users_registration_date_id_idx;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+CREATE INDEX IF NOT EXISTS articles_creation_date_id_idx ON articles (creation_date, id);
+CREATE INDEX IF NOT EXISTS articles_user_id_creation_date_idx ON articles (user_id, creation_date, id);
+CREATE INDEX IF NOT EXISTS users_registration_date_id_idx ON users (registration_date, id);
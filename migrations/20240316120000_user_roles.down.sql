@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+ALTER TABLE articles DROP CONSTRAINT IF EXISTS articles_user_id_fkey;
+ALTER TABLE articles
+    ADD CONSTRAINT articles_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users(id);
+
+ALTER TABLE users DROP COLUMN IF EXISTS role;
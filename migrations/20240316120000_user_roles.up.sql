@@ -0,0 +1,11 @@
+-- Add up migration script here
+
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS role VARCHAR(20) DEFAULT 'author' NOT NULL
+    CHECK (role IN ('reader', 'author', 'editor', 'admin'));
+
+-- Deleting an account removes its articles as well.
+ALTER TABLE articles DROP CONSTRAINT IF EXISTS articles_user_id_fkey;
+ALTER TABLE articles
+    ADD CONSTRAINT articles_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
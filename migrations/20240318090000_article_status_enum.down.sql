@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+ALTER TABLE articles ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE articles ALTER COLUMN status TYPE VARCHAR(20) USING status::text;
+ALTER TABLE articles ALTER COLUMN status SET DEFAULT 'draft';
+
+DROP TYPE IF EXISTS article_status;
@@ -0,0 +1,12 @@
+-- Add up migration script here
+
+CREATE TYPE article_status AS ENUM (
+    'draft', 'in_review', 'scheduled', 'published', 'unlisted', 'archived'
+);
+
+UPDATE articles SET status = 'draft'
+WHERE status NOT IN ('draft', 'in_review', 'scheduled', 'published', 'unlisted', 'archived');
+
+ALTER TABLE articles ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE articles ALTER COLUMN status TYPE article_status USING status::article_status;
+ALTER TABLE articles ALTER COLUMN status SET DEFAULT 'draft';
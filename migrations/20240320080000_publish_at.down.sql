@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+DROP INDEX IF EXISTS articles_due_idx;
+
+UPDATE articles SET status = 'draft' WHERE status = 'scheduled';
+
+ALTER TABLE articles DROP CONSTRAINT IF EXISTS articles_scheduled_publish_at;
+ALTER TABLE articles DROP COLUMN IF EXISTS publish_at;
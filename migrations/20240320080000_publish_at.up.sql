@@ -0,0 +1,10 @@
+-- Add up migration script here
+
+ALTER TABLE articles ADD COLUMN publish_at TIMESTAMPTZ;
+
+ALTER TABLE articles ADD CONSTRAINT articles_scheduled_publish_at
+    CHECK (status <> 'scheduled' OR publish_at IS NOT NULL);
+
+CREATE INDEX IF NOT EXISTS articles_due_idx
+    ON articles (publish_at)
+    WHERE status = 'scheduled';
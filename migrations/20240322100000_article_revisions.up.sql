@@ -0,0 +1,19 @@
+-- Add up migration script here
+
+CREATE TABLE IF NOT EXISTS article_revisions (
+    id VARCHAR(50) PRIMARY KEY,
+    article_id VARCHAR(50) NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    editor_id VARCHAR(50) REFERENCES users(id) ON DELETE SET NULL,
+    title VARCHAR(255) NOT NULL,
+    content TEXT NOT NULL,
+    status article_status NOT NULL,
+    creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS article_revisions_article_idx
+    ON article_revisions (article_id, creation_date, id);
+
+-- Existing articles start their history with their current text.
+INSERT INTO article_revisions (id, article_id, editor_id, title, content, status, creation_date)
+SELECT gen_random_uuid()::text, id, user_id, title, content, status, creation_date
+FROM articles;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS article_slugs;
+
+ALTER TABLE articles DROP CONSTRAINT IF EXISTS articles_user_id_slug_key;
+ALTER TABLE articles DROP COLUMN IF EXISTS slug;
@@ -0,0 +1,32 @@
+-- Add up migration script here
+
+ALTER TABLE articles ADD COLUMN slug VARCHAR(100);
+
+-- Existing articles get a slug from their title. Titles that clash for the
+-- same author are told apart by the start of the article id.
+WITH base AS (
+    SELECT id, user_id, creation_date,
+        COALESCE(NULLIF(left(trim(BOTH '-' FROM lower(regexp_replace(title, '[^a-zA-Z0-9]+', '-', 'g'))), 80), ''), 'article') AS slug
+    FROM articles
+), numbered AS (
+    SELECT id, slug, row_number() OVER (PARTITION BY user_id, slug ORDER BY creation_date, id) AS n
+    FROM base
+)
+UPDATE articles
+SET slug = CASE WHEN numbered.n = 1 THEN numbered.slug ELSE numbered.slug || '-' || left(articles.id, 8) END
+FROM numbered
+WHERE numbered.id = articles.id;
+
+ALTER TABLE articles ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE articles ADD CONSTRAINT articles_user_id_slug_key UNIQUE (user_id, slug);
+
+-- Slugs an article had before its title changed, kept so old links redirect.
+CREATE TABLE IF NOT EXISTS article_slugs (
+    user_id VARCHAR(50) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    slug VARCHAR(100) NOT NULL,
+    article_id VARCHAR(50) NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_id, slug)
+);
+
+CREATE INDEX IF NOT EXISTS article_slugs_article_id_idx ON article_slugs (article_id);
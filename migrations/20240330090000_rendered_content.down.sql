@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+ALTER TABLE article_revisions DROP COLUMN IF EXISTS content_html;
+
+ALTER TABLE articles DROP COLUMN IF EXISTS content_text;
+ALTER TABLE articles DROP COLUMN IF EXISTS toc;
+ALTER TABLE articles DROP COLUMN IF EXISTS content_html;
@@ -0,0 +1,11 @@
+-- Add up migration script here
+
+-- Rendered from the Markdown in `content` by the server. Rows written
+-- before this migration are rendered when migrations are applied.
+ALTER TABLE articles ADD COLUMN content_html TEXT;
+ALTER TABLE articles ADD COLUMN toc JSONB;
+-- The article as plain text, without Markdown syntax or raw HTML. Search
+-- snippets are cut from it.
+ALTER TABLE articles ADD COLUMN content_text TEXT;
+
+ALTER TABLE article_revisions ADD COLUMN content_html TEXT;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+ALTER TABLE articles DROP COLUMN IF EXISTS reading_time;
+ALTER TABLE articles DROP COLUMN IF EXISTS word_count;
+ALTER TABLE articles DROP COLUMN IF EXISTS excerpt;
@@ -0,0 +1,6 @@
+-- Add up migration script here
+
+-- Computed from `content` by the server, like `content_html`.
+ALTER TABLE articles ADD COLUMN excerpt TEXT;
+ALTER TABLE articles ADD COLUMN word_count INTEGER;
+ALTER TABLE articles ADD COLUMN reading_time INTEGER;
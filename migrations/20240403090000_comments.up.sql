@@ -0,0 +1,17 @@
+-- Add up migration script here
+
+CREATE TABLE IF NOT EXISTS comments (
+    id VARCHAR(50) PRIMARY KEY,
+    article_id VARCHAR(50) NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    user_id VARCHAR(50) REFERENCES users(id) ON DELETE SET NULL,
+    parent_id VARCHAR(50) REFERENCES comments(id) ON DELETE CASCADE,
+    -- 0 for comments on the article, 1 for replies to those, and so on.
+    depth INTEGER NOT NULL DEFAULT 0,
+    content TEXT NOT NULL,
+    deleted BOOLEAN NOT NULL DEFAULT false,
+    creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    update_date TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS comments_article_id_idx ON comments (article_id, creation_date, id);
+CREATE INDEX IF NOT EXISTS comments_parent_id_idx ON comments (parent_id, creation_date, id);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS reactions;
+DROP TYPE IF EXISTS reaction_kind;
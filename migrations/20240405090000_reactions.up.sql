@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+CREATE TYPE reaction_kind AS ENUM ('like', 'insightful', 'funny', 'love', 'celebrate');
+
+-- One row per user, article and kind; counts are aggregated from the rows,
+-- so concurrent reactions can never drift from the truth.
+CREATE TABLE IF NOT EXISTS reactions (
+    article_id VARCHAR(50) NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    user_id VARCHAR(50) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind reaction_kind NOT NULL,
+    creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (article_id, user_id, kind)
+);
+
+CREATE INDEX IF NOT EXISTS reactions_article_kind_idx ON reactions (article_id, kind, creation_date);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+DROP INDEX IF EXISTS articles_published_user_idx;
+ALTER TABLE articles DROP COLUMN IF EXISTS published_at;
+DROP TABLE IF EXISTS follows;
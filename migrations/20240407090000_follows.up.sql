@@ -0,0 +1,35 @@
+-- Add up migration script here
+
+CREATE TABLE IF NOT EXISTS follows (
+    follower_id VARCHAR(50) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    followee_id VARCHAR(50) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (follower_id, followee_id),
+    CHECK (follower_id <> followee_id)
+);
+
+-- The primary key serves "who does X follow" and the feed; this serves
+-- "who follows X".
+CREATE INDEX IF NOT EXISTS follows_followee_idx ON follows (followee_id, creation_date, follower_id);
+
+-- When the article last became published. Set by the server on every move
+-- to `published`, by hand or by the scheduled publisher.
+ALTER TABLE articles ADD COLUMN published_at TIMESTAMP;
+
+-- Published articles get the best estimate history allows: the scheduled
+-- time, the start of their latest published streak of revisions, or their
+-- creation.
+UPDATE articles SET published_at = COALESCE(
+    publish_at AT TIME ZONE 'UTC',
+    (SELECT MIN(revision.creation_date) FROM article_revisions revision
+        WHERE revision.article_id = articles.id AND revision.status = 'published'
+            AND NOT EXISTS (SELECT 1 FROM article_revisions later
+                WHERE later.article_id = revision.article_id
+                    AND later.creation_date > revision.creation_date
+                    AND later.status <> 'published')),
+    creation_date)
+WHERE status = 'published';
+
+-- Lets the feed walk each followed author's published articles newest first.
+CREATE INDEX IF NOT EXISTS articles_published_user_idx
+    ON articles (user_id, published_at, id) WHERE status = 'published';
@@ -0,0 +1,32 @@
+-- Add up migration script here
+
+CREATE TABLE IF NOT EXISTS bookmarks (
+    user_id VARCHAR(50) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    article_id VARCHAR(50) NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (user_id, article_id)
+);
+
+CREATE INDEX IF NOT EXISTS bookmarks_article_id_idx ON bookmarks (article_id);
+
+CREATE TABLE IF NOT EXISTS reading_lists (
+    id VARCHAR(50) PRIMARY KEY,
+    user_id VARCHAR(50) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    is_public BOOLEAN NOT NULL DEFAULT false,
+    position INTEGER NOT NULL,
+    creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    UNIQUE (user_id, name)
+);
+
+-- Entries disappear with their article, so deleted articles never linger
+-- in anyone's lists.
+CREATE TABLE IF NOT EXISTS reading_list_items (
+    list_id VARCHAR(50) NOT NULL REFERENCES reading_lists(id) ON DELETE CASCADE,
+    article_id VARCHAR(50) NOT NULL REFERENCES articles(id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    PRIMARY KEY (list_id, article_id)
+);
+
+CREATE INDEX IF NOT EXISTS reading_list_items_article_id_idx ON reading_list_items (article_id);
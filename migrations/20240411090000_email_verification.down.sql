@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+DROP TABLE IF EXISTS email_tokens;
+UPDATE users SET account_status = 'active' WHERE account_status = 'pending_verification';
+ALTER TABLE users ALTER COLUMN account_status SET DEFAULT 'active';
+ALTER TABLE users DROP COLUMN IF EXISTS pending_email;
@@ -0,0 +1,22 @@
+-- Add up migration script here
+
+-- New accounts wait for their email address to be confirmed; existing ones
+-- stay active.
+ALTER TABLE users ALTER COLUMN account_status SET DEFAULT 'pending_verification';
+
+-- A new address the user asked to switch to. It replaces `email` once a
+-- token sent to it is redeemed.
+ALTER TABLE users ADD COLUMN pending_email VARCHAR;
+
+-- Single-use tokens sent by email. The token itself is a signed JWT whose
+-- `jti` is the id of its row here.
+CREATE TABLE IF NOT EXISTS email_tokens (
+    id VARCHAR(50) PRIMARY KEY,
+    user_id VARCHAR(50) NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    purpose VARCHAR(30) NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used BOOLEAN NOT NULL DEFAULT false,
+    creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS email_tokens_user_id_idx ON email_tokens (user_id, purpose);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+DROP INDEX IF EXISTS email_tokens_token_hash_idx;
+ALTER TABLE email_tokens DROP COLUMN IF EXISTS token_hash;
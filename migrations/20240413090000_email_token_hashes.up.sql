@@ -0,0 +1,9 @@
+-- Add up migration script here
+
+-- Tokens sent by email are looked up by their SHA-256 digest, like refresh
+-- tokens. Tokens issued before this have no digest and stop working.
+ALTER TABLE email_tokens ADD COLUMN IF NOT EXISTS token_hash VARCHAR(64);
+UPDATE email_tokens SET used = true, token_hash = id WHERE token_hash IS NULL;
+ALTER TABLE email_tokens ALTER COLUMN token_hash SET NOT NULL;
+
+CREATE UNIQUE INDEX IF NOT EXISTS email_tokens_token_hash_idx ON email_tokens (token_hash);
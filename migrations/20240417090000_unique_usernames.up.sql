@@ -0,0 +1,12 @@
+-- Add up migration script here
+
+-- Permalinks find an author by username, so no two users may share one,
+-- whatever the case. Existing clashes keep the oldest account's name; the
+-- others get the start of their id appended.
+UPDATE users SET username = username || '-' || left(id, 8)
+WHERE EXISTS (
+    SELECT 1 FROM users earlier
+    WHERE lower(earlier.username) = lower(users.username)
+        AND (earlier.registration_date, earlier.id) < (users.registration_date, users.id));
+
+CREATE UNIQUE INDEX IF NOT EXISTS users_username_idx ON users (lower(username));
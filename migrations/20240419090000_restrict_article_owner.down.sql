@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+ALTER TABLE articles DROP CONSTRAINT IF EXISTS articles_user_id_fkey;
+ALTER TABLE articles
+    ADD CONSTRAINT articles_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE;
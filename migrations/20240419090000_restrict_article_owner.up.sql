@@ -0,0 +1,8 @@
+-- Add up migration script here
+
+-- Deleting an account must not take its articles with it; accounts with
+-- articles cannot be deleted.
+ALTER TABLE articles DROP CONSTRAINT IF EXISTS articles_user_id_fkey;
+ALTER TABLE articles
+    ADD CONSTRAINT articles_user_id_fkey
+    FOREIGN KEY (user_id) REFERENCES users(id);
@@ -0,0 +1,209 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::{ dev::Payload, http::header, web, FromRequest, HttpRequest };
+use chrono::{ Duration, Utc };
+use jsonwebtoken::{ DecodingKey, EncodingKey, Header, Validation };
+use serde::{ Serialize, Deserialize };
+use sqlx::PgPool;
+use sha2::{ Digest, Sha256 };
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::db::token_table_helper;
+use crate::errors::{ AppError, AppResult };
+use crate::models::AuthTokens;
+use crate::permissions::{ self, Role };
+
+/// Lifetime of an access token in seconds.
+pub const ACCESS_TOKEN_TTL: i64 = 15 * 60;
+
+/// Lifetime of a refresh token in days.
+pub const REFRESH_TOKEN_TTL_DAYS: i64 = 30;
+
+/// Lifetime of an email verification token in hours.
+pub const VERIFICATION_TOKEN_TTL_HOURS: i64 = 24;
+
+/// Lifetime of a password reset token in minutes.
+pub const RESET_TOKEN_TTL_MINUTES: i64 = 60;
+
+/// Time in minutes to complete a login with a second factor.
+pub const LOGIN_CHALLENGE_TTL_MINUTES: i64 = 5;
+
+/// What a single-use token may be used for. Each purpose signs with its own
+/// key, so a token cannot be replayed for another purpose or as an access
+/// token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenPurpose {
+    VerifyEmail,
+    /// Confirming a new address before it replaces the current one.
+    ChangeEmail,
+    ResetPassword,
+    /// Finishing a login that needs a second factor.
+    LoginChallenge,
+}
+
+impl TokenPurpose {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::VerifyEmail => "verify_email",
+            TokenPurpose::ChangeEmail => "change_email",
+            TokenPurpose::ResetPassword => "reset_password",
+            TokenPurpose::LoginChallenge => "login_challenge",
+        }
+    }
+}
+
+/// Claims of a single-use token. `jti` is the id of the stored token;
+/// only the token's digest is kept, and it is marked used when redeemed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SingleUseClaims {
+    pub sub: String,
+    pub jti: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// The user an access token was issued to. Add it as a handler argument
+/// to require a valid `Authorization: Bearer <token>` header from an active
+/// account; suspended and unverified accounts are turned away here, before
+/// any handler runs. As `Option<AuthUser>` they are treated as anonymous.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: String,
+    /// Read from the database on every request, like the account status.
+    pub role: Role,
+}
+
+impl FromRequest for AuthUser {
+    type Error = AppError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { authenticate(&req).await })
+    }
+}
+
+async fn authenticate(req: &HttpRequest) -> AppResult<AuthUser> {
+    let config = req.app_data::<web::Data<Config>>()
+        .ok_or_else(|| AppError::Internal("Config is not registered".to_string()))?;
+    let pool = req.app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| AppError::Internal("Database pool is not registered".to_string()))?;
+
+    let token = req.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(|| unauthorized("Missing bearer token"))?;
+
+    let claims = decode_access_token(config, token)
+        .map_err(|_| unauthorized("Invalid or expired token"))?;
+    let role = permissions::active_role(pool, &claims.sub).await?;
+
+    Ok(AuthUser { id: claims.sub, role })
+}
+
+fn unauthorized(message: &str) -> AppError {
+    AppError::Unauthorized(message.to_string())
+}
+
+pub fn issue_access_token(config: &Config, user_id: &str) -> AppResult<String> {
+    let now = Utc::now().timestamp();
+    let claims = Claims {
+        sub: user_id.to_string(),
+        iat: now,
+        exp: now + ACCESS_TOKEN_TTL,
+    };
+
+    jsonwebtoken::encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(config.jwt_secret.as_bytes()))
+        .map_err(AppError::from)
+}
+
+pub fn decode_access_token(config: &Config, token: &str) -> AppResult<Claims> {
+    jsonwebtoken::decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
+        &Validation::default())
+        .map(|data| data.claims)
+        .map_err(AppError::from)
+}
+
+/// Stores a single-use token for `user_id` and returns it signed, ready
+/// to be sent by email or handed to the client.
+pub async fn issue_single_use_token(pool: &PgPool, config: &Config, user_id: &str, purpose: TokenPurpose, ttl: Duration) -> AppResult<String> {
+    let now = Utc::now();
+    let claims = SingleUseClaims {
+        sub: user_id.to_string(),
+        jti: Uuid::new_v4().hyphenated().to_string(),
+        iat: now.timestamp(),
+        exp: (now + ttl).timestamp(),
+    };
+
+    let token = jsonwebtoken::encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(single_use_token_secret(config, purpose).as_bytes()))?;
+
+    token_table_helper::insert_email_token(
+        pool, &claims.jti, user_id, purpose.as_str(), &token, (now + ttl).naive_utc()).await?;
+
+    Ok(token)
+}
+
+/// Checks a single-use token and marks it used, returning the id of the
+/// user it was issued to.
+pub async fn redeem_single_use_token(pool: &PgPool, config: &Config, purpose: TokenPurpose, token: &str) -> AppResult<String> {
+    let claims = jsonwebtoken::decode::<SingleUseClaims>(
+        token,
+        &DecodingKey::from_secret(single_use_token_secret(config, purpose).as_bytes()),
+        &Validation::default())
+        .map_err(|_| unauthorized("Invalid or expired token"))?
+        .claims;
+
+    let user_id = token_table_helper::consume_email_token(pool, token, purpose.as_str()).await?;
+    if user_id != claims.sub {
+        return Err(unauthorized("Invalid or expired token"));
+    }
+
+    Ok(user_id)
+}
+
+fn single_use_token_secret(config: &Config, purpose: TokenPurpose) -> String {
+    format!("{}:{}", config.jwt_secret, purpose.as_str())
+}
+
+/// Issues a fresh access token together with a refresh token. Passing the
+/// `family_id` of a rotated refresh token keeps the new one in the same
+/// family so reuse of an old token can revoke the whole chain.
+pub async fn issue_tokens(pool: &PgPool, config: &Config, user_id: &str, family_id: Option<String>) -> AppResult<AuthTokens> {
+    let access_token = issue_access_token(config, user_id)?;
+    let refresh_token = token_table_helper::insert_refresh_token(pool, user_id, family_id).await?;
+
+    Ok(AuthTokens {
+        access_token,
+        refresh_token,
+        token_type: "Bearer".to_string(),
+        expires_in: ACCESS_TOKEN_TTL,
+    })
+}
+
+/// Refresh tokens are opaque random strings; only their SHA-256 digest is
+/// stored.
+pub fn generate_refresh_token() -> String {
+    format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
@@ -0,0 +1,38 @@
+pub const USAGE: &str = "\
+Usage: inklink_backend [COMMAND]
+
+Commands:
+  serve            Start the HTTP server (default)
+  migrate up       Apply every pending migration
+  migrate down     Revert the most recently applied migration
+  migrate status   List migrations and whether they are applied";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Serve,
+    Migrate(MigrateCommand),
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MigrateCommand {
+    Up,
+    Down,
+    Status,
+}
+
+/// Parses the arguments that follow the program name.
+pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let args: Vec<String> = args.into_iter().collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args.as_slice() {
+        [] | ["serve"] => Ok(Command::Serve),
+        ["migrate", "up"] => Ok(Command::Migrate(MigrateCommand::Up)),
+        ["migrate", "down"] => Ok(Command::Migrate(MigrateCommand::Down)),
+        ["migrate", "status"] => Ok(Command::Migrate(MigrateCommand::Status)),
+        ["migrate", ..] => Err("migrate expects one of: up, down, status".to_string()),
+        ["help"] | ["-h"] | ["--help"] => Ok(Command::Help),
+        _ => Err(format!("unknown command: {}", args.join(" "))),
+    }
+}
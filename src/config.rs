@@ -0,0 +1,303 @@
+use std::{ env, fmt, fs, io };
+use std::path::Path;
+
+use dotenv::dotenv;
+use lettre::message::Mailbox;
+use serde::Deserialize;
+
+/// File read when `INKLINK_CONFIG` is not set. It is optional; environment
+/// variables alone are enough to start the server.
+const DEFAULT_CONFIG_FILE: &str = "inklink.toml";
+
+const INVALID_TOTP_KEY: &str =
+    "totp_key (TOTP_KEY) must be 64 hex characters, e.g. from `openssl rand -hex 32`";
+
+/// Server settings, loaded once at startup from an optional TOML file and
+/// then overridden by environment variables.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    pub database_url: String,
+    pub database: PoolConfig,
+    /// Apply pending migrations before the server starts accepting requests.
+    pub auto_migrate: bool,
+    pub log_level: String,
+    pub bcrypt_cost: u32,
+    pub cors_origins: Vec<String>,
+    pub jwt_secret: String,
+    /// Hex encoded 256-bit key that encrypts stored TOTP secrets. Only
+    /// required to serve, see `require_totp_key`.
+    pub totp_key: String,
+    /// Seconds between runs of the scheduled article publisher.
+    pub publish_interval: u64,
+    /// How deeply replies to comments may nest; 0 disables replies.
+    pub comment_max_depth: i32,
+    /// Base URL of the web app, for links sent by email.
+    pub app_url: String,
+    pub mail: MailConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    /// Seconds to wait for a free connection before failing the request.
+    pub acquire_timeout: u64,
+    /// Seconds an unused connection is kept open.
+    pub idle_timeout: u64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MailConfig {
+    /// `smtp`, or `outbox` to write emails to `outbox_dir` instead of
+    /// sending them.
+    pub transport: String,
+    pub from: String,
+    pub outbox_dir: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: String,
+    pub smtp_password: String,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(String, io::Error),
+    Parse(String, toml::de::Error),
+    Env(&'static str, String),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "cannot read {}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "cannot parse {}: {}", path, e),
+            ConfigError::Env(key, value) => write!(f, "{} has an invalid value {:?}", key, value),
+            ConfigError::Invalid(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            host: "127.0.0.1".to_string(),
+            port: 4000,
+            database_url: String::new(),
+            database: PoolConfig::default(),
+            auto_migrate: false,
+            log_level: "actix_web=info".to_string(),
+            bcrypt_cost: bcrypt::DEFAULT_COST,
+            cors_origins: Vec::new(),
+            jwt_secret: String::new(),
+            totp_key: String::new(),
+            publish_interval: 30,
+            comment_max_depth: 5,
+            app_url: "http://localhost:3000".to_string(),
+            mail: MailConfig::default(),
+        }
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            max_connections: 10,
+            acquire_timeout: 5,
+            idle_timeout: 600,
+        }
+    }
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        MailConfig {
+            transport: "outbox".to_string(),
+            from: "Inklink <no-reply@localhost>".to_string(),
+            outbox_dir: "outbox".to_string(),
+            smtp_host: String::new(),
+            smtp_port: 587,
+            smtp_username: String::new(),
+            smtp_password: String::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Config, ConfigError> {
+        dotenv().ok();
+
+        let mut config = match env::var("INKLINK_CONFIG") {
+            Ok(path) => Config::from_file(&path)?,
+            Err(_) if Path::new(DEFAULT_CONFIG_FILE).exists() => Config::from_file(DEFAULT_CONFIG_FILE)?,
+            Err(_) => Config::default(),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    /// Fails unless `totp_key` is set. `validate` checks its format but lets
+    /// it be empty, so `migrate` runs without the key.
+    pub fn require_totp_key(&self) -> Result<(), ConfigError> {
+        if self.totp_key.is_empty() {
+            return Err(ConfigError::Invalid(INVALID_TOTP_KEY.to_string()));
+        }
+
+        Ok(())
+    }
+
+    pub fn bind_address(&self) -> (String, u16) {
+        (self.host.clone(), self.port)
+    }
+
+    fn from_file(path: &str) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| ConfigError::Read(path.to_string(), e))?;
+
+        toml::from_str(&contents)
+            .map_err(|e| ConfigError::Parse(path.to_string(), e))
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Ok(host) = env::var("HOST") {
+            self.host = host;
+        }
+        if let Some(port) = parse_env("PORT")? {
+            self.port = port;
+        }
+        if let Ok(database_url) = env::var("DATABASE_URL") {
+            self.database_url = database_url;
+        }
+        if let Some(max_connections) = parse_env("DATABASE_MAX_CONNECTIONS")? {
+            self.database.max_connections = max_connections;
+        }
+        if let Some(acquire_timeout) = parse_env("DATABASE_ACQUIRE_TIMEOUT")? {
+            self.database.acquire_timeout = acquire_timeout;
+        }
+        if let Some(idle_timeout) = parse_env("DATABASE_IDLE_TIMEOUT")? {
+            self.database.idle_timeout = idle_timeout;
+        }
+        if let Some(auto_migrate) = parse_env("AUTO_MIGRATE")? {
+            self.auto_migrate = auto_migrate;
+        }
+        if let Ok(log_level) = env::var("LOG_LEVEL") {
+            self.log_level = log_level;
+        }
+        if let Some(bcrypt_cost) = parse_env("BCRYPT_COST")? {
+            self.bcrypt_cost = bcrypt_cost;
+        }
+        if let Ok(origins) = env::var("CORS_ORIGINS") {
+            self.cors_origins = origins
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect();
+        }
+        if let Ok(jwt_secret) = env::var("JWT_SECRET") {
+            self.jwt_secret = jwt_secret;
+        }
+        if let Ok(totp_key) = env::var("TOTP_KEY") {
+            self.totp_key = totp_key;
+        }
+        if let Some(publish_interval) = parse_env("PUBLISH_INTERVAL")? {
+            self.publish_interval = publish_interval;
+        }
+        if let Some(comment_max_depth) = parse_env("COMMENT_MAX_DEPTH")? {
+            self.comment_max_depth = comment_max_depth;
+        }
+        if let Ok(app_url) = env::var("APP_URL") {
+            self.app_url = app_url;
+        }
+        if let Ok(transport) = env::var("MAIL_TRANSPORT") {
+            self.mail.transport = transport;
+        }
+        if let Ok(from) = env::var("MAIL_FROM") {
+            self.mail.from = from;
+        }
+        if let Ok(outbox_dir) = env::var("MAIL_OUTBOX_DIR") {
+            self.mail.outbox_dir = outbox_dir;
+        }
+        if let Ok(smtp_host) = env::var("SMTP_HOST") {
+            self.mail.smtp_host = smtp_host;
+        }
+        if let Some(smtp_port) = parse_env("SMTP_PORT")? {
+            self.mail.smtp_port = smtp_port;
+        }
+        if let Ok(smtp_username) = env::var("SMTP_USERNAME") {
+            self.mail.smtp_username = smtp_username;
+        }
+        if let Ok(smtp_password) = env::var("SMTP_PASSWORD") {
+            self.mail.smtp_password = smtp_password;
+        }
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.database_url.is_empty() {
+            return Err(ConfigError::Invalid("DATABASE_URL must be set".to_string()));
+        }
+        if self.jwt_secret.is_empty() {
+            return Err(ConfigError::Invalid("JWT_SECRET must be set".to_string()));
+        }
+        if !self.totp_key.is_empty()
+            && (self.totp_key.len() != 64 || !self.totp_key.chars().all(|c| c.is_ascii_hexdigit())) {
+            return Err(ConfigError::Invalid(INVALID_TOTP_KEY.to_string()));
+        }
+        if self.port == 0 {
+            return Err(ConfigError::Invalid("port must be between 1 and 65535".to_string()));
+        }
+        if self.database.max_connections == 0 {
+            return Err(ConfigError::Invalid("database.max_connections must be at least 1".to_string()));
+        }
+        if self.publish_interval == 0 {
+            return Err(ConfigError::Invalid("publish_interval must be at least 1 second".to_string()));
+        }
+        if self.comment_max_depth < 0 {
+            return Err(ConfigError::Invalid("comment_max_depth cannot be negative".to_string()));
+        }
+        if !self.app_url.starts_with("http://") && !self.app_url.starts_with("https://") {
+            return Err(ConfigError::Invalid(format!("invalid app_url {:?}", self.app_url)));
+        }
+        match self.mail.transport.as_str() {
+            "smtp" if self.mail.smtp_host.is_empty() =>
+                return Err(ConfigError::Invalid("mail.smtp_host must be set for the smtp transport".to_string())),
+            "smtp" | "outbox" => {},
+            transport => return Err(ConfigError::Invalid(format!("unknown mail transport {:?}", transport))),
+        }
+        if self.mail.from.parse::<Mailbox>().is_err() {
+            return Err(ConfigError::Invalid(format!("invalid mail.from address {:?}", self.mail.from)));
+        }
+        if !(4..=31).contains(&self.bcrypt_cost) {
+            return Err(ConfigError::Invalid("bcrypt_cost must be between 4 and 31".to_string()));
+        }
+        if let Some(origin) = self.cors_origins.iter().find(|origin| !is_valid_origin(origin)) {
+            return Err(ConfigError::Invalid(format!("invalid CORS origin {:?}", origin)));
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_env<T: std::str::FromStr>(key: &'static str) -> Result<Option<T>, ConfigError> {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| ConfigError::Env(key, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+fn is_valid_origin(origin: &str) -> bool {
+    origin == "*"
+        || ((origin.starts_with("http://") || origin.starts_with("https://"))
+            && !origin.ends_with('/'))
+}
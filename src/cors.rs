@@ -0,0 +1,20 @@
+use actix_cors::Cors;
+use actix_web::http::{ header, Method };
+
+const MAX_AGE: usize = 86400;
+
+/// Builds the CORS middleware for the configured origins. `"*"` allows any
+/// origin. Requests from other origins get no CORS headers, and with no
+/// origins configured responses are left untouched.
+pub fn from_config(origins: &[String]) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE, Method::OPTIONS])
+        .allowed_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+        .max_age(MAX_AGE);
+
+    if origins.iter().any(|origin| origin == "*") {
+        return cors.allow_any_origin().send_wildcard();
+    }
+
+    origins.iter().fold(cors, |cors, origin| cors.allowed_origin(origin))
+}
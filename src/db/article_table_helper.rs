@@ -1,260 +1,634 @@
-use dotenv::dotenv;
+use std::collections::{ BTreeMap, HashSet };
+
+use chrono::{ DateTime, Utc };
 use uuid::Uuid;
-use std::env;
-use sqlx::{ Pool, Postgres };
+use sqlx::{ PgConnection, PgPool, Postgres, QueryBuilder };
 use sqlx::postgres::PgQueryResult;
+use sqlx::types::Json;
 
+use crate::errors::{ AppError, AppResult };
+use crate::markdown;
 use crate::models::{
-    Article, InsertArticle, ReturnArticle, UpdateArticle
+    Article, ArticleFilters, ArticleStatus, InsertArticle, ReactionKind, ReturnArticle,
+    SearchResult, Tag, UpdateArticle
 };
+use crate::markdown::TocEntry;
+use crate::pagination::{ Cursor, OffsetPage, Page, Paginated };
+use crate::permissions::{ Permission, Role };
+use crate::search;
+use crate::slug;
+use crate::db::{ revision_table_helper, tag_table_helper };
 use crate::db::user_table_helper::get_user_by_id;
 
-
-pub async fn insert_article(article: InsertArticle) -> Result<PgQueryResult, String> {
-    let pool = establish_connection().await;
+pub async fn insert_article(pool: &PgPool, user_id: String, article: InsertArticle) -> AppResult<String> {
     let article_id = Uuid::new_v4().hyphenated().to_string();
 
-    if let Some(user) = get_user_by_id(article.user_id.clone()).await {
-        if user.account_status != "active" {
-            return Err("User account is not active".to_string());
-        }
+    let user = get_user_by_id(pool, user_id.clone()).await?;
+    if user.account_status != "active" {
+        return Err(AppError::Forbidden("User account is not active".to_string()));
+    }
 
-    } else {
-        return Err("User not found".to_string());
+    let status = scheduled_status(article.status, article.publish_at)?.unwrap_or(ArticleStatus::Draft);
+    if !status.is_initial() {
+        return Err(AppError::Validation(format!("Articles cannot be created as {}", status)));
     }
 
-    let query = if article.status.is_none() {
-        sqlx::query!(
-            r#"INSERT INTO articles (id, user_id, title, content)
-            VALUES ($1, $2, $3, $4)"#,
-            article_id, article.user_id, article.title, article.content)
-    } else {
-        sqlx::query!(
-            r#"INSERT INTO articles (id, user_id, title, content, status)
-            VALUES ($1, $2, $3, $4, $5)"#,
-            article_id, article.user_id, article.title, article.content, article.status)
-    };
+    if status == ArticleStatus::Scheduled && article.publish_at.is_none() {
+        return Err(AppError::Validation("publish_at is required to schedule an article".to_string()));
+    }
 
-    let result = query.execute(&pool).await;
+    let tags = tag_table_helper::parse_tags(&article.tags)?;
 
-    pool.close().await;
+    let mut tx = pool.begin().await?;
 
-    match result {
-        Ok(res) => Ok(res),
-        Err(e) => Err(e.to_string())
-    }
+    let slug = unique_slug(&mut tx, &user_id, &article_id, &article.title).await?;
+    let rendered = markdown::render(&article.content);
+
+    sqlx::query!(
+        r#"INSERT INTO articles (id, user_id, title, slug, content, content_html, toc,
+            content_text, excerpt, word_count, reading_time, status, publish_at, published_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13,
+            CASE WHEN $12 = 'published'::article_status THEN now() END)"#,
+        article_id, user_id, article.title, slug, article.content,
+        rendered.html, Json(&rendered.toc) as _, rendered.text,
+        rendered.excerpt, rendered.word_count, rendered.reading_time,
+        status as ArticleStatus, article.publish_at)
+        .execute(&mut *tx)
+        .await?;
+
+    revision_table_helper::insert_revision(
+        &mut tx, &article_id, Some(&user_id), &article.title, &article.content, &rendered.html, status).await?;
+
+    tag_table_helper::set_article_tags(&mut tx, &article_id, &tags).await?;
+
+    tx.commit().await?;
+
+    Ok(article_id)
 }
 
-pub async fn update_article(article: UpdateArticle) -> Result<PgQueryResult, String> {
-    let pool = establish_connection().await;
+/// Applies an update on behalf of `user_id` and records the result as a new
+/// revision. Only the author may change an article unless `role` grants
+/// editing any article.
+pub async fn update_article(pool: &PgPool, user_id: &str, role: Role, article: UpdateArticle) -> AppResult<()> {
+    let existing = get_article_by_id(pool, article.id.clone()).await?;
 
-    if let None = get_article_by_id(article.id.clone()).await {
-        return Err("Article not found".to_string());
+    if existing.user_id != user_id && !role.can(Permission::EditAnyArticle) {
+        return Err(AppError::Forbidden("Only the author or an editor can update this article".to_string()));
     }
 
-    let mut params: Vec<String> = Vec::new();
-    let mut params_index = 1;
-    let mut query = String::from("UPDATE articles SET");
+    let status = scheduled_status(article.status, article.publish_at)?;
 
-    if let Some(title) = article.title {
-        query.push_str(format!(" title = ${}", params_index).as_str());
-        params_index += 1;
-        query.push_str(",");
-        params.push(title);
+    if status == Some(ArticleStatus::Scheduled)
+        && article.publish_at.is_none()
+        && existing.status != ArticleStatus::Scheduled {
+        return Err(AppError::Validation("publish_at is required to schedule an article".to_string()));
     }
 
-    if let Some(content) = article.content {
-        query.push_str(format!(" content = ${}", params_index).as_str());
-        params_index += 1;
-        query.push_str(",");
-        params.push(content);
+    if let Some(status) = status {
+        let allowed = if role.can(Permission::EditAnyArticle) {
+            existing.status.editor_can_transition_to(status)
+        } else {
+            existing.status.can_transition_to(status)
+        };
+        if !allowed {
+            return Err(AppError::Conflict(format!(
+                "Cannot move article from {} to {}", existing.status, status)));
+        }
     }
 
-    if let Some(status) = article.status {
-        query.push_str(format!(" status = ${}", params_index).as_str());
-        params_index += 1;
-        query.push_str(",");
-        params.push(status);
+    let tags = article.tags.as_deref().map(tag_table_helper::parse_tags).transpose()?;
+    let changes_text = article.title.is_some() || article.content.is_some() || status.is_some();
+
+    if !changes_text && tags.is_none() {
+        return Err(AppError::Validation("No fields to update".to_string()));
     }
 
-    query.pop();
-    query.push_str(format!(" WHERE id = ${};", params_index).as_str());
-    params.push(article.id);
-    println!("{}", query);
-    println!("{:?}", params);
+    let mut tx = pool.begin().await?;
 
-    let mut sql = sqlx::query(&query);
+    if changes_text {
+        if let Some(title) = &article.title {
+            rename_slug(&mut tx, &existing, title).await?;
+        }
 
-    for param in params {
-        sql = sql.bind(param);
-    }
+        let mut query = QueryBuilder::<Postgres>::new("UPDATE articles SET ");
+        let mut fields = query.separated(", ");
+
+        if let Some(title) = article.title {
+            fields.push("title = ");
+            fields.push_bind_unseparated(title);
+        }
+
+        if let Some(content) = article.content {
+            let rendered = markdown::render(&content);
+
+            fields.push("content = ");
+            fields.push_bind_unseparated(content);
+            fields.push("content_html = ");
+            fields.push_bind_unseparated(rendered.html);
+            fields.push("toc = ");
+            fields.push_bind_unseparated(Json(rendered.toc));
+            fields.push("content_text = ");
+            fields.push_bind_unseparated(rendered.text);
+            fields.push("excerpt = ");
+            fields.push_bind_unseparated(rendered.excerpt);
+            fields.push("word_count = ");
+            fields.push_bind_unseparated(rendered.word_count);
+            fields.push("reading_time = ");
+            fields.push_bind_unseparated(rendered.reading_time);
+        }
+
+        if let Some(status) = status {
+            fields.push("status = ");
+            fields.push_bind_unseparated(status);
+
+            if status == ArticleStatus::Published && existing.status != ArticleStatus::Published {
+                fields.push("published_at = now()");
+            }
+        }
+
+        // Leaving `scheduled` by hand cancels the pending publication.
+        match (status, article.publish_at) {
+            (_, Some(publish_at)) => {
+                fields.push("publish_at = ");
+                fields.push_bind_unseparated(publish_at);
+            },
+            (Some(status), None) if status != ArticleStatus::Scheduled => {
+                fields.push("publish_at = NULL");
+            },
+            _ => {}
+        }
+
+        query.push(" WHERE id = ");
+        query.push_bind(article.id.clone());
+        query.push(" RETURNING title, content, COALESCE(content_html, ''), status");
 
-    let result = sql
-        .execute(&pool)
-        .await;
+        let (title, content, content_html, status) = query
+            .build_query_as::<(String, String, String, ArticleStatus)>()
+            .fetch_one(&mut *tx)
+            .await?;
 
-    match result {
-        Ok(res) => Ok(res),
-        Err(e) => Err(e.to_string())
+        revision_table_helper::insert_revision(
+            &mut tx, &article.id, Some(user_id), &title, &content, &content_html, status).await?;
     }
-}
 
-pub async fn get_article_by_id(id: String) -> Option<Article> {
-    let pool = establish_connection().await;
+    if let Some(tags) = tags {
+        tag_table_helper::set_article_tags(&mut tx, &article.id, &tags).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
 
+pub async fn get_article_by_id(pool: &PgPool, id: String) -> AppResult<Article> {
     sqlx::query_as!(
         Article,
         r#"
-        SELECT id, user_id, title, content, status, creation_date
+        SELECT id, user_id, title, slug, content,
+            status as "status: ArticleStatus", creation_date, publish_at
         FROM articles
         WHERE id = $1
         "#,
         id
     )
-    .fetch_one(&pool)
-    .await
-    .ok()
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Article not found".to_string()))
 }
 
-pub async fn delete_article(id: String, user_id: String) -> Result<PgQueryResult, String> {
-    let pool = establish_connection().await;
-    let article = get_article_by_id(id.clone()).await;
-
-    if article.is_none() {
-        return Err("Article not found".to_string());
-    }
-
-    let article = article.unwrap();
+/// Deletes an unpublished article of `user_id`. Its bookmarks, reading list
+/// entries, comments and reactions go with it.
+pub async fn delete_article(pool: &PgPool, id: String, user_id: String) -> AppResult<PgQueryResult> {
+    let article = get_article_by_id(pool, id.clone()).await?;
 
     if article.user_id != user_id {
-        return Err("Unauthorized".to_string());
+        return Err(AppError::Forbidden("Only the author can delete this article".to_string()));
     }
 
-    if article.status == "published" {
-        return Err("Cannot delete published article".to_string());
+    if article.status == ArticleStatus::Published {
+        return Err(AppError::Conflict("Cannot delete published article".to_string()));
     }
 
     let result = sqlx::query!(
         r#"DELETE FROM articles WHERE id = $1"#,
         id
     )
-    .execute(&pool)
-    .await;
-    println!("{:?}", result);
+    .execute(pool)
+    .await?;
 
-    pool.close().await;
+    Ok(result)
+}
+
+/// Fetches an article with its author. Published and unlisted articles are
+/// visible to anyone with the id, other statuses only to their author;
+/// everyone else gets a not found error.
+pub async fn get_article(pool: &PgPool, article_id: String, viewer_id: Option<&str>) -> AppResult<ReturnArticle> {
+    let article = sqlx::query_as!(
+        ReturnArticle,
+        r#"SELECT articles.id, username as author, title, slug,
+            content as content_markdown, COALESCE(content_html, '') as "content_html!",
+            COALESCE(toc, '[]') as "toc!: Json<Vec<TocEntry>>",
+            COALESCE(excerpt, '') as "excerpt!", COALESCE(word_count, 0) as "word_count!",
+            COALESCE(reading_time, 0) as "reading_time!",
+            (SELECT COUNT(*) FROM comments
+                WHERE comments.article_id = articles.id AND NOT comments.deleted) as "comment_count!",
+            users.id as user_id, status as "status: ArticleStatus",
+            creation_date, publish_at, published_at,
+            COALESCE((SELECT json_agg(json_build_object('slug', tags.slug, 'name', tags.name) ORDER BY tags.slug)
+                FROM article_tags INNER JOIN tags ON tags.id = article_tags.tag_id
+                WHERE article_tags.article_id = articles.id), '[]') as "tags!: Json<Vec<Tag>>",
+            COALESCE((SELECT json_object_agg(kind, count) FROM (
+                SELECT kind, COUNT(*) as count FROM reactions
+                WHERE reactions.article_id = articles.id GROUP BY kind) counts), '{}')
+                as "reactions!: Json<BTreeMap<String, i64>>",
+            COALESCE((SELECT json_agg(kind ORDER BY kind) FROM reactions
+                WHERE reactions.article_id = articles.id AND reactions.user_id = $2), '[]')
+                as "my_reactions!: Json<Vec<ReactionKind>>",
+            EXISTS(SELECT 1 FROM bookmarks
+                WHERE bookmarks.article_id = articles.id AND bookmarks.user_id = $2) as "bookmarked!"
+            FROM articles
+            INNER JOIN users ON articles.user_id = users.id
+            WHERE articles.id = $1"#,
+        article_id, viewer_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Article not found".to_string()))?;
 
-    match result {
-        Ok(res) => Ok(res),
-        Err(e) => Err(e.to_string())
+    if !article.status.is_public() && viewer_id != Some(article.user_id.as_str()) {
+        return Err(AppError::NotFound("Article not found".to_string()));
     }
+
+    Ok(article)
 }
 
-pub async fn get_articles_by_user_id(user_id: String, type_: String) -> Vec<ReturnArticle> {
-    let pool = establish_connection().await;
+/// Fetches an article by its author's username and its current slug, with
+/// the same visibility rules as `get_article`.
+pub async fn get_article_by_slug(pool: &PgPool, username: &str, slug: &str, viewer_id: Option<&str>) -> AppResult<ReturnArticle> {
+    let article_id = sqlx::query_scalar!(
+        r#"SELECT articles.id
+        FROM articles
+        INNER JOIN users ON articles.user_id = users.id
+        WHERE lower(users.username) = lower($1) AND articles.slug = $2"#,
+        username, slug)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Article not found".to_string()))?;
 
-    if type_ == "all" {
-        return sqlx::query_as!(
-            ReturnArticle,
-            r#"
-            SELECT articles.id, username as author, title,
-            users.id as user_id, content, status, creation_date
-            FROM articles
-            INNER JOIN users ON articles.user_id = users.id
-            WHERE user_id = $1
-            "#, user_id)
-            .fetch_all(&pool)
-            .await
-            .expect("Error retrieving records from table");
+    get_article(pool, article_id, viewer_id).await
+}
+
+/// Looks up a slug an article used to have and returns its current one, if
+/// the article is visible to `viewer_id`.
+pub async fn renamed_slug(pool: &PgPool, username: &str, old_slug: &str, viewer_id: Option<&str>) -> AppResult<Option<String>> {
+    let slug = sqlx::query_scalar!(
+        r#"SELECT articles.slug
+        FROM article_slugs
+        INNER JOIN articles ON article_slugs.article_id = articles.id
+        INNER JOIN users ON article_slugs.user_id = users.id
+        WHERE lower(users.username) = lower($1) AND article_slugs.slug = $2
+            AND (articles.status IN ('published', 'unlisted') OR articles.user_id = $3)"#,
+        username, old_slug, viewer_id)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(slug)
+}
+
+/// Lists articles one page at a time. Published articles are visible to
+/// everyone, other statuses only to their author (`viewer_id`). Bookmarks
+/// also list the unlisted articles they point to.
+pub async fn list_articles(pool: &PgPool, filters: &ArticleFilters, viewer_id: Option<&str>, page: &Page) -> AppResult<Paginated<ReturnArticle>> {
+    let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+    push_article_columns(&mut query, viewer_id);
+    query.push(r#"
+        FROM articles
+        INNER JOIN users ON articles.user_id = users.id
+        WHERE "#);
+
+    let visible = if filters.bookmarked_by.is_some() {
+        "status IN ('published', 'unlisted')"
     } else {
-        return sqlx::query_as!(
-            ReturnArticle,
-            r#"
-            SELECT articles.id, username as author, title,
-            users.id as user_id, content, status, creation_date
-            FROM articles
-            INNER JOIN users ON articles.user_id = users.id
-            WHERE user_id = $1 AND status = $2
-            "#,
-            user_id, type_
-            )
-            .fetch_all(&pool)
-            .await
-            .expect("Error retrieving records from table");
+        "status = 'published'"
+    };
+
+    match viewer_id {
+        Some(viewer_id) => {
+            query.push(format!("({} OR articles.user_id = ", visible));
+            query.push_bind(viewer_id.to_string());
+            query.push(")");
+        },
+        None => {
+            query.push(visible);
+        }
+    }
+
+    if let Some(author) = &filters.author {
+        query.push(" AND users.username = ");
+        query.push_bind(author.clone());
+    }
+
+    if let Some(tag) = &filters.tag {
+        query.push(" AND EXISTS (SELECT 1 FROM article_tags INNER JOIN tags ON tags.id = article_tags.tag_id WHERE article_tags.article_id = articles.id AND tags.slug = ");
+        query.push_bind(tag.clone());
+        query.push(")");
+    }
+
+    if let Some(author_id) = &filters.author_id {
+        query.push(" AND articles.user_id = ");
+        query.push_bind(author_id.clone());
+    }
+
+    if let Some(user_id) = &filters.bookmarked_by {
+        query.push(" AND articles.id IN (SELECT article_id FROM bookmarks WHERE user_id = ");
+        query.push_bind(user_id.clone());
+        query.push(")");
+    }
+
+    if let Some(status) = &filters.status {
+        query.push(" AND status = ");
+        query.push_bind(*status);
     }
+
+    page.push_keyset(&mut query, "articles.creation_date", "articles.id");
+
+    let articles = query
+        .build_query_as::<ReturnArticle>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(page.finish(articles, |article| Cursor {
+        timestamp: article.creation_date,
+        id: article.id.clone(),
+    }))
 }
 
-pub async fn get_latest_articles() -> Vec<ReturnArticle> {
-    let pool = establish_connection().await;
+/// Lists the published articles of the authors `follower_id` follows, by
+/// when they were published. Each author's articles are read newest first
+/// from `articles_published_user_idx` and cut at the page size before they
+/// are merged, so a page costs one short index scan per followed author
+/// however much those authors have written.
+pub async fn list_feed_articles(pool: &PgPool, follower_id: &str, page: &Page) -> AppResult<Paginated<ReturnArticle>> {
+    let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+    push_article_columns(&mut query, Some(follower_id));
+    query.push(r#"
+        FROM (
+            SELECT feed.id, feed.published_at FROM follows
+            CROSS JOIN LATERAL (
+                SELECT articles.id, articles.published_at FROM articles
+                WHERE articles.user_id = follows.followee_id AND articles.status = 'published'"#);
+    page.push_keyset(&mut query, "articles.published_at", "articles.id");
+    query.push(r#"
+            ) feed
+            WHERE follows.follower_id = "#);
+    query.push_bind(follower_id.to_string());
+    page.push_keyset(&mut query, "feed.published_at", "feed.id");
+    query.push(r#"
+        ) page
+        INNER JOIN articles ON articles.id = page.id
+        INNER JOIN users ON articles.user_id = users.id"#);
+    page.push_order_by(&mut query, "page.published_at", "page.id");
+
+    let articles = query
+        .build_query_as::<ReturnArticle>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(page.finish(articles, |article| Cursor {
+        timestamp: article.published_at.unwrap_or(article.creation_date),
+        id: article.id.clone(),
+    }))
+}
 
-    let result = sqlx::query_as!(
-        ReturnArticle,
-        r#"
-        SELECT articles.id, username as author, title,
-        users.id as user_id, content, status, creation_date
-        FROM articles
+/// Lists the articles on a reading list in the list's order, skipping the
+/// ones `viewer_id` may not see.
+pub async fn list_reading_list_articles(pool: &PgPool, list_id: &str, viewer_id: Option<&str>) -> AppResult<Vec<ReturnArticle>> {
+    let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+    push_article_columns(&mut query, viewer_id);
+    query.push(r#"
+        FROM reading_list_items
+        INNER JOIN articles ON reading_list_items.article_id = articles.id
         INNER JOIN users ON articles.user_id = users.id
-        WHERE status = 'published'
-        ORDER BY creation_date DESC
-        LIMIT 10
-        "#)
-        .fetch_all(&pool)
-        .await
-        .expect("Error retrieving records from table");
+        WHERE reading_list_items.list_id = "#);
+    query.push_bind(list_id.to_string());
+    query.push(" AND (status IN ('published', 'unlisted') OR articles.user_id = ");
+    query.push_bind(viewer_id.map(str::to_string));
+    query.push(") ORDER BY reading_list_items.position, reading_list_items.creation_date");
+
+    Ok(query.build_query_as::<ReturnArticle>().fetch_all(pool).await?)
+}
+
+/// Full-text search over titles and content, best matches first. `tsquery`
+/// must already be in `to_tsquery` syntax. Visibility is the same as for
+/// `list_articles`. Snippets are cut from the plain text of the article and
+/// returned as escaped HTML with matches in `<mark>`.
+pub async fn search_articles(pool: &PgPool, tsquery: &str, viewer_id: Option<&str>, page: &OffsetPage) -> AppResult<Paginated<SearchResult>> {
+    let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+    push_article_columns(&mut query, viewer_id);
+    query.push(r#",
+        ts_rank(search_vector, query) as rank,
+        ts_headline('english', COALESCE(content_text, ''), query, "#);
+    query.push_bind(search::headline_options());
+    query.push(r#") as snippet
+        FROM articles
+        INNER JOIN users ON articles.user_id = users.id,
+        to_tsquery('english', "#);
+    query.push_bind(tsquery.to_string());
+    query.push(") query WHERE search_vector @@ query AND ");
+
+    match viewer_id {
+        Some(viewer_id) => {
+            query.push("(status = 'published' OR articles.user_id = ");
+            query.push_bind(viewer_id.to_string());
+            query.push(")");
+        },
+        None => {
+            query.push("status = 'published'");
+        }
+    }
 
-    pool.close().await;
+    query.push(" ORDER BY rank DESC, creation_date DESC, articles.id");
+    page.push_limit(&mut query);
 
-    result
+    let mut results = query
+        .build_query_as::<SearchResult>()
+        .fetch_all(pool)
+        .await?;
+
+    for result in &mut results {
+        result.snippet = search::highlight(&result.snippet);
+    }
+
+    Ok(page.finish(results))
 }
 
-#[allow(dead_code)]
-pub async fn get_article(article_id: String) -> Option<ReturnArticle> {
-    let pool = establish_connection().await;
+/// Renders the HTML and metadata of articles and revisions written before
+/// content was rendered on save. Returns how many rows were filled in.
+pub async fn render_missing_html(pool: &PgPool) -> AppResult<u64> {
+    let mut rendered = 0;
 
-    let result = sqlx::query_as!(
-        ReturnArticle,
-        r#"SELECT articles.id, username as author, title, content,
-            users.id as user_id, status, creation_date
-            FROM articles
-            INNER JOIN users ON articles.user_id = users.id
-            WHERE articles.id = $1"#,
-        article_id)
-        .fetch_one(&pool)
-        .await;
+    let articles = sqlx::query!(
+        r#"SELECT id, content FROM articles
+        WHERE content_html IS NULL OR toc IS NULL OR content_text IS NULL
+            OR excerpt IS NULL OR word_count IS NULL OR reading_time IS NULL"#)
+        .fetch_all(pool)
+        .await?;
 
-    match result {
-        Ok(res) => Some(res),
-        Err(_) => None
+    for article in articles {
+        let output = markdown::render(&article.content);
+
+        sqlx::query!(
+            r#"UPDATE articles
+            SET content_html = $1, toc = $2, content_text = $3, excerpt = $4,
+                word_count = $5, reading_time = $6
+            WHERE id = $7"#,
+            output.html, Json(&output.toc) as _, output.text, output.excerpt,
+            output.word_count, output.reading_time, article.id)
+            .execute(pool)
+            .await?;
+        rendered += 1;
     }
+
+    let revisions = sqlx::query!(
+        r#"SELECT id, content FROM article_revisions WHERE content_html IS NULL"#)
+        .fetch_all(pool)
+        .await?;
+
+    for revision in revisions {
+        sqlx::query!(
+            r#"UPDATE article_revisions SET content_html = $1 WHERE id = $2"#,
+            markdown::render(&revision.content).html, revision.id)
+            .execute(pool)
+            .await?;
+        rendered += 1;
+    }
+
+    Ok(rendered)
 }
 
-pub async fn get_all_articles() -> Vec<ReturnArticle> {
-    let pool = establish_connection().await;
+/// Publishes up to `batch` scheduled articles whose time has come and
+/// returns their ids. Rows already locked by another server instance are
+/// skipped, so concurrent publishers never pick up the same article. Each
+/// publication is recorded as a revision without an editor.
+pub async fn publish_due_articles(pool: &PgPool, batch: i64) -> AppResult<Vec<String>> {
+    let mut tx = pool.begin().await?;
+
+    let published = sqlx::query!(
+        r#"UPDATE articles SET status = 'published', published_at = now()
+        WHERE id IN (
+            SELECT id FROM articles
+            WHERE status = 'scheduled' AND publish_at <= now()
+            ORDER BY publish_at
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING id, title, content, COALESCE(content_html, '') as "content_html!",
+            status as "status: ArticleStatus""#,
+        batch)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    for article in &published {
+        revision_table_helper::insert_revision(
+            &mut tx, &article.id, None, &article.title, &article.content, &article.content_html, article.status).await?;
+    }
 
-    let result = sqlx::query_as!(
-        ReturnArticle,
-        r#"
-        SELECT articles.id, username as author, title, content,
-        users.id as user_id, status, creation_date
-        FROM articles
-        INNER JOIN users ON articles.user_id = users.id
-        WHERE status = 'published'
-        ORDER BY creation_date;
-        "#)
-        .fetch_all(&pool)
-        .await
-        .expect("Error retrieving records from table");
+    tx.commit().await?;
 
-    pool.close().await;
+    Ok(published.into_iter().map(|article| article.id).collect())
+}
 
-    result
+/// Pushes the select list of `ReturnArticle`, over `articles` joined with
+/// `users`, onto a query built at runtime. `my_reactions` are the reactions
+/// of `viewer_id`.
+fn push_article_columns(query: &mut QueryBuilder<'_, Postgres>, viewer_id: Option<&str>) {
+    query.push(r#"articles.id, username as author, title, slug,
+        content as content_markdown, COALESCE(content_html, '') as content_html,
+        COALESCE(toc, '[]') as toc, COALESCE(excerpt, '') as excerpt,
+        COALESCE(word_count, 0) as word_count, COALESCE(reading_time, 0) as reading_time,
+        (SELECT COUNT(*) FROM comments
+            WHERE comments.article_id = articles.id AND NOT comments.deleted) as comment_count,
+        users.id as user_id, status, articles.creation_date, publish_at, articles.published_at,
+        COALESCE((SELECT json_agg(json_build_object('slug', tags.slug, 'name', tags.name) ORDER BY tags.slug)
+            FROM article_tags INNER JOIN tags ON tags.id = article_tags.tag_id
+            WHERE article_tags.article_id = articles.id), '[]') as tags,
+        COALESCE((SELECT json_object_agg(kind, count) FROM (
+            SELECT kind, COUNT(*) as count FROM reactions
+            WHERE reactions.article_id = articles.id GROUP BY kind) counts), '{}') as reactions,
+        COALESCE((SELECT json_agg(kind ORDER BY kind) FROM reactions
+            WHERE reactions.article_id = articles.id AND reactions.user_id = "#);
+    query.push_bind(viewer_id.map(str::to_string));
+    query.push(r#"), '[]') as my_reactions,
+        EXISTS(SELECT 1 FROM bookmarks
+            WHERE bookmarks.article_id = articles.id AND bookmarks.user_id = "#);
+    query.push_bind(viewer_id.map(str::to_string));
+    query.push(") as bookmarked");
 }
 
-async fn establish_connection() -> Pool<Postgres> {
-    dotenv().ok();
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    sqlx::postgres::PgPoolOptions::new()
-        .connect(&database_url)
-        .await
-        .expect("Failed to connect to Postgres")
+/// Picks a slug for `title` that no other article by `user_id` uses or used
+/// to use, adding `-2`, `-3`, ... when needed.
+async fn unique_slug(conn: &mut PgConnection, user_id: &str, article_id: &str, title: &str) -> AppResult<String> {
+    let base = slug::article_slug(title);
+
+    // Slugs only contain letters, digits and dashes, so there is nothing to
+    // escape in the LIKE pattern.
+    let taken: HashSet<String> = sqlx::query_scalar!(
+        r#"SELECT slug as "slug!" FROM articles
+        WHERE user_id = $1 AND id <> $2 AND (slug = $3 OR slug LIKE $3 || '-%')
+        UNION
+        SELECT slug FROM article_slugs
+        WHERE user_id = $1 AND article_id <> $2 AND (slug = $3 OR slug LIKE $3 || '-%')"#,
+        user_id, article_id, base)
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .collect();
+
+    Ok(slug::first_free(base, &taken))
+}
+
+/// Moves an article to the slug of its new title. The old slug is kept so
+/// links to it redirect to the new one.
+async fn rename_slug(conn: &mut PgConnection, article: &Article, title: &str) -> AppResult<()> {
+    let slug = unique_slug(conn, &article.user_id, &article.id, title).await?;
+    if slug == article.slug {
+        return Ok(());
+    }
+
+    sqlx::query!(
+        r#"INSERT INTO article_slugs (user_id, slug, article_id) VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, slug) DO NOTHING"#,
+        article.user_id, article.slug, article.id)
+        .execute(&mut *conn)
+        .await?;
+
+    // Going back to an earlier title takes its slug out of the history.
+    sqlx::query!(
+        r#"DELETE FROM article_slugs WHERE user_id = $1 AND slug = $2"#,
+        article.user_id, slug)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query!(
+        r#"UPDATE articles SET slug = $1 WHERE id = $2"#,
+        slug, article.id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Works out the status implied by `publish_at`: giving a publish time
+/// schedules the article, and it has to lie in the future.
+fn scheduled_status(status: Option<ArticleStatus>, publish_at: Option<DateTime<Utc>>) -> AppResult<Option<ArticleStatus>> {
+    match (status, publish_at) {
+        (None | Some(ArticleStatus::Scheduled), Some(publish_at)) => {
+            if publish_at <= Utc::now() {
+                return Err(AppError::Validation("publish_at must be in the future".to_string()));
+            }
+
+            Ok(Some(ArticleStatus::Scheduled))
+        },
+        (Some(_), Some(_)) => Err(AppError::Validation("publish_at can only be set on scheduled articles".to_string())),
+        (status, None) => Ok(status),
+    }
 }
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+use sqlx::{ PgPool, Postgres, QueryBuilder };
+
+use crate::errors::{ AppError, AppResult };
+use crate::models::{ Comment, CommentThread, InsertComment };
+use crate::pagination::{ Cursor, Page, Paginated };
+use crate::permissions::{ Permission, Role };
+
+const MAX_COMMENT_LENGTH: usize = 10_000;
+/// Replies listed under each comment; the rest are paged through with
+/// `parent_id`.
+const INLINE_REPLIES: i64 = 5;
+
+/// Select list for `Comment`, over `comments` joined with `users`. Deleted
+/// comments are returned without author or content, and are left out of
+/// `reply_count`.
+const COMMENT_COLUMNS: &str = r#"comments.id, comments.article_id, comments.parent_id,
+    CASE WHEN comments.deleted THEN NULL ELSE comments.user_id END as user_id,
+    CASE WHEN comments.deleted THEN NULL ELSE users.username END as author,
+    CASE WHEN comments.deleted THEN NULL ELSE comments.content END as content,
+    comments.deleted, comments.depth,
+    (SELECT COUNT(*) FROM comments replies
+        WHERE replies.parent_id = comments.id AND NOT replies.deleted) as reply_count,
+    comments.creation_date, comments.update_date"#;
+
+/// The columns of a comment needed to check what may be done with it.
+struct StoredComment {
+    id: String,
+    article_id: String,
+    user_id: Option<String>,
+    depth: i32,
+    deleted: bool,
+}
+
+async fn get_stored_comment(pool: &PgPool, id: &str) -> AppResult<StoredComment> {
+    sqlx::query_as!(
+        StoredComment,
+        r#"SELECT id, article_id, user_id, depth, deleted FROM comments WHERE id = $1"#,
+        id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Comment not found".to_string()))
+}
+
+/// Adds a comment by `user_id` to an article, or a reply when `parent_id` is
+/// set. Replies may nest at most `max_depth` levels deep.
+pub async fn insert_comment(pool: &PgPool, article_id: &str, user_id: &str, comment: InsertComment, max_depth: i32) -> AppResult<String> {
+    let content = validate_content(&comment.content)?;
+
+    let depth = match &comment.parent_id {
+        Some(parent_id) => {
+            let parent = get_stored_comment(pool, parent_id).await?;
+
+            if parent.article_id != article_id {
+                return Err(AppError::NotFound("Comment not found".to_string()));
+            }
+            if parent.deleted {
+                return Err(AppError::Conflict("Cannot reply to a deleted comment".to_string()));
+            }
+            if parent.depth >= max_depth {
+                return Err(AppError::Validation(format!("Replies cannot be nested more than {} levels deep", max_depth)));
+            }
+
+            parent.depth + 1
+        },
+        None => 0,
+    };
+
+    let comment_id = Uuid::new_v4().hyphenated().to_string();
+
+    sqlx::query!(
+        r#"INSERT INTO comments (id, article_id, user_id, parent_id, depth, content)
+        VALUES ($1, $2, $3, $4, $5, $6)"#,
+        comment_id, article_id, user_id, comment.parent_id, depth, content)
+        .execute(pool)
+        .await?;
+
+    Ok(comment_id)
+}
+
+/// Changes the text of a comment. Only its author may edit it.
+pub async fn update_comment(pool: &PgPool, id: &str, user_id: &str, content: &str) -> AppResult<()> {
+    let comment = get_stored_comment(pool, id).await?;
+
+    if comment.user_id.as_deref() != Some(user_id) {
+        return Err(AppError::Forbidden("Only the author can edit this comment".to_string()));
+    }
+    if comment.deleted {
+        return Err(AppError::Conflict("Cannot edit a deleted comment".to_string()));
+    }
+
+    let content = validate_content(content)?;
+
+    sqlx::query!(
+        r#"UPDATE comments SET content = $1, update_date = CURRENT_TIMESTAMP WHERE id = $2"#,
+        content, comment.id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Soft-deletes a comment: replies stay in place under an empty comment.
+/// Besides the comment's author, the article's author and editors may
+/// remove comments.
+pub async fn delete_comment(pool: &PgPool, id: &str, user_id: &str, role: Role) -> AppResult<()> {
+    let comment = get_stored_comment(pool, id).await?;
+
+    let article_author = sqlx::query_scalar!(
+        r#"SELECT user_id FROM articles WHERE id = $1"#,
+        comment.article_id)
+        .fetch_one(pool)
+        .await?;
+
+    if comment.user_id.as_deref() != Some(user_id)
+        && article_author != user_id
+        && !role.can(Permission::EditAnyArticle) {
+        return Err(AppError::Forbidden("You cannot delete this comment".to_string()));
+    }
+
+    sqlx::query!(
+        r#"UPDATE comments SET deleted = true, content = '', update_date = CURRENT_TIMESTAMP
+        WHERE id = $1"#,
+        comment.id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Lists the comments on an article one page at a time, oldest first, each
+/// with up to `depth` levels of replies, `INLINE_REPLIES` per comment. With
+/// `parent_id` the replies to that comment are listed instead.
+pub async fn list_comments(pool: &PgPool, article_id: &str, parent_id: Option<&str>, depth: i32, page: &Page) -> AppResult<Paginated<CommentThread>> {
+    if let Some(parent_id) = parent_id {
+        let parent = get_stored_comment(pool, parent_id).await?;
+        if parent.article_id != article_id {
+            return Err(AppError::NotFound("Comment not found".to_string()));
+        }
+    }
+
+    let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+    query.push(COMMENT_COLUMNS);
+    query.push(" FROM comments LEFT JOIN users ON comments.user_id = users.id WHERE comments.article_id = ");
+    query.push_bind(article_id.to_string());
+
+    match parent_id {
+        Some(parent_id) => {
+            query.push(" AND comments.parent_id = ");
+            query.push_bind(parent_id.to_string());
+        },
+        None => {
+            query.push(" AND comments.parent_id IS NULL");
+        }
+    }
+
+    page.push_keyset(&mut query, "comments.creation_date", "comments.id");
+
+    let comments = query
+        .build_query_as::<Comment>()
+        .fetch_all(pool)
+        .await?;
+
+    let page = page.finish(comments, |comment| Cursor {
+        timestamp: comment.creation_date,
+        id: comment.id.clone(),
+    });
+
+    let ids: Vec<String> = page.items.iter().map(|comment| comment.id.clone()).collect();
+    let mut replies = group_by_parent(load_replies(pool, &ids, depth).await?);
+
+    Ok(Paginated {
+        items: page.items.into_iter().map(|comment| thread(comment, &mut replies)).collect(),
+        next_cursor: page.next_cursor,
+        prev_cursor: page.prev_cursor,
+    })
+}
+
+/// Loads the first `INLINE_REPLIES` replies to each of `parent_ids`, and
+/// theirs, down to `depth` levels, oldest first.
+async fn load_replies(pool: &PgPool, parent_ids: &[String], depth: i32) -> AppResult<Vec<Comment>> {
+    if depth < 1 || parent_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        r#"WITH RECURSIVE tree AS (
+            SELECT reply.id, 1 AS level FROM UNNEST("#);
+    query.push_bind(parent_ids.to_vec());
+    query.push(r#"::VARCHAR[]) parent(id)
+            CROSS JOIN LATERAL (
+                SELECT id FROM comments WHERE comments.parent_id = parent.id
+                ORDER BY creation_date, id LIMIT "#);
+    query.push_bind(INLINE_REPLIES);
+    query.push(r#"
+            ) reply
+            UNION ALL
+            SELECT reply.id, tree.level + 1 FROM tree
+            CROSS JOIN LATERAL (
+                SELECT id FROM comments WHERE comments.parent_id = tree.id
+                ORDER BY creation_date, id LIMIT "#);
+    query.push_bind(INLINE_REPLIES);
+    query.push(r#"
+            ) reply
+            WHERE tree.level < "#);
+    query.push_bind(depth);
+    query.push(r#"
+        )
+        SELECT "#);
+    query.push(COMMENT_COLUMNS);
+    query.push(r#"
+        FROM tree
+        INNER JOIN comments ON comments.id = tree.id
+        LEFT JOIN users ON comments.user_id = users.id
+        ORDER BY comments.creation_date, comments.id"#);
+
+    Ok(query.build_query_as::<Comment>().fetch_all(pool).await?)
+}
+
+fn group_by_parent(comments: Vec<Comment>) -> HashMap<String, Vec<Comment>> {
+    let mut grouped: HashMap<String, Vec<Comment>> = HashMap::new();
+
+    for comment in comments {
+        if let Some(parent_id) = comment.parent_id.clone() {
+            grouped.entry(parent_id).or_default().push(comment);
+        }
+    }
+
+    grouped
+}
+
+fn thread(comment: Comment, replies: &mut HashMap<String, Vec<Comment>>) -> CommentThread {
+    let children = replies.remove(&comment.id).unwrap_or_default();
+
+    CommentThread {
+        replies: children.into_iter().map(|child| thread(child, replies)).collect(),
+        comment,
+    }
+}
+
+fn validate_content(content: &str) -> AppResult<&str> {
+    let content = content.trim();
+
+    if content.is_empty() {
+        return Err(AppError::Validation("Comment cannot be empty".to_string()));
+    }
+    if content.chars().count() > MAX_COMMENT_LENGTH {
+        return Err(AppError::Validation(format!("Comments can be at most {} characters long", MAX_COMMENT_LENGTH)));
+    }
+
+    Ok(content)
+}
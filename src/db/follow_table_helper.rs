@@ -0,0 +1,82 @@
+use sqlx::{ PgPool, Postgres, QueryBuilder };
+
+use crate::errors::{ AppError, AppResult };
+use crate::models::Follow;
+use crate::pagination::{ Cursor, Page, Paginated };
+
+/// Makes `follower_id` follow an active user. Following someone twice is a
+/// no-op.
+pub async fn follow(pool: &PgPool, follower_id: &str, followee_id: &str) -> AppResult<()> {
+    if follower_id == followee_id {
+        return Err(AppError::Validation("You cannot follow yourself".to_string()));
+    }
+
+    let result = sqlx::query!(
+        r#"INSERT INTO follows (follower_id, followee_id)
+        SELECT $1, id FROM users WHERE id = $2 AND account_status = 'active'
+        ON CONFLICT DO NOTHING"#,
+        follower_id, followee_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 && !is_following(pool, follower_id, followee_id).await? {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    Ok(())
+}
+
+pub async fn unfollow(pool: &PgPool, follower_id: &str, followee_id: &str) -> AppResult<()> {
+    sqlx::query!(
+        r#"DELETE FROM follows WHERE follower_id = $1 AND followee_id = $2"#,
+        follower_id, followee_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+async fn is_following(pool: &PgPool, follower_id: &str, followee_id: &str) -> AppResult<bool> {
+    let following = sqlx::query_scalar!(
+        r#"SELECT EXISTS(SELECT 1 FROM follows WHERE follower_id = $1 AND followee_id = $2) as "exists!""#,
+        follower_id, followee_id)
+        .fetch_one(pool)
+        .await?;
+
+    Ok(following)
+}
+
+/// Lists the users following `user_id`.
+pub async fn list_followers(pool: &PgPool, user_id: &str, page: &Page) -> AppResult<Paginated<Follow>> {
+    list_follows(pool, "follows.followee_id", "follows.follower_id", user_id, page).await
+}
+
+/// Lists the users `user_id` follows.
+pub async fn list_following(pool: &PgPool, user_id: &str, page: &Page) -> AppResult<Paginated<Follow>> {
+    list_follows(pool, "follows.follower_id", "follows.followee_id", user_id, page).await
+}
+
+/// Lists the users on the `other_column` side of the follows whose
+/// `user_column` is `user_id`, by when the follow started.
+async fn list_follows(pool: &PgPool, user_column: &str, other_column: &str, user_id: &str, page: &Page) -> AppResult<Paginated<Follow>> {
+    let mut query = QueryBuilder::<Postgres>::new(format!(
+        r#"SELECT users.id, username, first_name || ' ' || last_name AS display_name,
+            follows.creation_date AS followed_at
+            FROM follows
+            INNER JOIN users ON users.id = {}
+            WHERE users.account_status = 'active' AND {} = "#,
+        other_column, user_column));
+    query.push_bind(user_id.to_string());
+
+    page.push_keyset(&mut query, "follows.creation_date", other_column);
+
+    let follows = query
+        .build_query_as::<Follow>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(page.finish(follows, |follow| Cursor {
+        timestamp: follow.followed_at,
+        id: follow.id.clone(),
+    }))
+}
@@ -0,0 +1,57 @@
+use sqlx::PgPool;
+use sqlx::migrate::{ Migrate, MigrateError, Migrator };
+
+/// Every migration in `migrations/`, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
+
+pub async fn run_pending(pool: &PgPool) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
+
+/// Reverts the most recently applied migration. Returns its version, or
+/// `None` when the database has nothing to revert.
+pub async fn revert_last(pool: &PgPool) -> Result<Option<i64>, MigrateError> {
+    let mut applied = applied_versions(pool).await?;
+    applied.sort_unstable();
+
+    let last = match applied.pop() {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+    let target = applied.last().copied().unwrap_or(0);
+
+    MIGRATOR.undo(pool, target).await?;
+    Ok(Some(last))
+}
+
+pub async fn status(pool: &PgPool) -> Result<Vec<MigrationStatus>, MigrateError> {
+    let applied = applied_versions(pool).await?;
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| migration.migration_type.is_up_migration())
+        .map(|migration| MigrationStatus {
+            version: migration.version,
+            description: migration.description.to_string(),
+            applied: applied.contains(&migration.version),
+        })
+        .collect())
+}
+
+async fn applied_versions(pool: &PgPool) -> Result<Vec<i64>, MigrateError> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect())
+}
@@ -1,2 +1,28 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
+
+use crate::config::Config;
+
+pub mod migrations;
 pub mod user_table_helper;
 pub mod article_table_helper;
+pub mod token_table_helper;
+pub mod revision_table_helper;
+pub mod tag_table_helper;
+pub mod comment_table_helper;
+pub mod reaction_table_helper;
+pub mod follow_table_helper;
+pub mod reading_list_table_helper;
+pub mod two_factor_table_helper;
+
+/// Builds the connection pool shared by every request.
+pub async fn create_pool(config: &Config) -> Result<PgPool, sqlx::Error> {
+    PgPoolOptions::new()
+        .max_connections(config.database.max_connections)
+        .acquire_timeout(Duration::from_secs(config.database.acquire_timeout))
+        .idle_timeout(Duration::from_secs(config.database.idle_timeout))
+        .connect(&config.database_url)
+        .await
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use sqlx::{ PgPool, Postgres, QueryBuilder };
+
+use crate::errors::AppResult;
+use crate::models::{ Reaction, ReactionKind };
+use crate::pagination::{ Cursor, Page, Paginated };
+
+/// Adds a reaction of `user_id` to an article. Reacting twice with the same
+/// kind is a no-op, so concurrent requests cannot count a reaction twice.
+pub async fn add_reaction(pool: &PgPool, article_id: &str, user_id: &str, kind: ReactionKind) -> AppResult<()> {
+    sqlx::query!(
+        r#"INSERT INTO reactions (article_id, user_id, kind) VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING"#,
+        article_id, user_id, kind as ReactionKind)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Removes a reaction of `user_id` from an article, if there is one.
+pub async fn remove_reaction(pool: &PgPool, article_id: &str, user_id: &str, kind: ReactionKind) -> AppResult<()> {
+    sqlx::query!(
+        r#"DELETE FROM reactions WHERE article_id = $1 AND user_id = $2 AND kind = $3"#,
+        article_id, user_id, kind as ReactionKind)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Number of reactions of each kind on an article. Kinds nobody used are
+/// left out.
+pub async fn reaction_counts(pool: &PgPool, article_id: &str) -> AppResult<BTreeMap<String, i64>> {
+    let counts = sqlx::query!(
+        r#"SELECT kind::text as "kind!", COUNT(*) as "count!" FROM reactions
+        WHERE article_id = $1 GROUP BY kind"#,
+        article_id)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(counts.into_iter().map(|row| (row.kind, row.count)).collect())
+}
+
+/// Lists who reacted to an article, oldest reaction first, optionally only
+/// with one kind.
+pub async fn list_reactions(pool: &PgPool, article_id: &str, kind: Option<ReactionKind>, page: &Page) -> AppResult<Paginated<Reaction>> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        r#"SELECT reactions.user_id, users.username, reactions.kind, reactions.creation_date
+        FROM reactions
+        INNER JOIN users ON reactions.user_id = users.id
+        WHERE reactions.article_id = "#);
+    query.push_bind(article_id.to_string());
+
+    if let Some(kind) = kind {
+        query.push(" AND reactions.kind = ");
+        query.push_bind(kind);
+    }
+
+    page.push_keyset(&mut query, "reactions.creation_date", "(reactions.user_id || '/' || reactions.kind::text)");
+
+    let reactions = query
+        .build_query_as::<Reaction>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(page.finish(reactions, |reaction| Cursor {
+        timestamp: reaction.creation_date,
+        id: format!("{}/{}", reaction.user_id, reaction.kind.as_str()),
+    }))
+}
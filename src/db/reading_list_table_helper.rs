@@ -0,0 +1,251 @@
+use std::collections::HashSet;
+
+use uuid::Uuid;
+use sqlx::{ PgConnection, PgPool };
+
+use crate::errors::{ AppError, AppResult };
+use crate::models::{ InsertReadingList, ReadingList, UpdateReadingList };
+
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Articles a single reading list may hold.
+pub const MAX_LIST_ARTICLES: i64 = 500;
+
+pub async fn add_bookmark(pool: &PgPool, user_id: &str, article_id: &str) -> AppResult<()> {
+    sqlx::query!(
+        r#"INSERT INTO bookmarks (user_id, article_id) VALUES ($1, $2) ON CONFLICT DO NOTHING"#,
+        user_id, article_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+pub async fn remove_bookmark(pool: &PgPool, user_id: &str, article_id: &str) -> AppResult<()> {
+    sqlx::query!(
+        r#"DELETE FROM bookmarks WHERE user_id = $1 AND article_id = $2"#,
+        user_id, article_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Lists a user's reading lists in their order. Private lists are only
+/// included for their owner.
+pub async fn list_reading_lists(pool: &PgPool, user_id: &str, include_private: bool) -> AppResult<Vec<ReadingList>> {
+    let lists = sqlx::query_as!(
+        ReadingList,
+        r#"SELECT id, user_id, name, is_public, position, creation_date,
+            (SELECT COUNT(*) FROM reading_list_items
+                WHERE reading_list_items.list_id = reading_lists.id) as "article_count!"
+        FROM reading_lists
+        WHERE user_id = $1 AND (is_public OR $2)
+        ORDER BY position, creation_date"#,
+        user_id, include_private)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(lists)
+}
+
+/// Fetches a reading list. Private lists are only visible to their owner;
+/// everyone else gets a not found error.
+pub async fn get_reading_list(pool: &PgPool, id: &str, viewer_id: Option<&str>) -> AppResult<ReadingList> {
+    sqlx::query_as!(
+        ReadingList,
+        r#"SELECT id, user_id, name, is_public, position, creation_date,
+            (SELECT COUNT(*) FROM reading_list_items
+                WHERE reading_list_items.list_id = reading_lists.id) as "article_count!"
+        FROM reading_lists
+        WHERE id = $1 AND (is_public OR user_id = $2)"#,
+        id, viewer_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Reading list not found".to_string()))
+}
+
+/// Fetches a reading list `user_id` may change: one of their own.
+pub async fn get_own_reading_list(pool: &PgPool, id: &str, user_id: &str) -> AppResult<ReadingList> {
+    let list = get_reading_list(pool, id, Some(user_id)).await?;
+
+    if list.user_id != user_id {
+        return Err(AppError::Forbidden("Only the owner can change this reading list".to_string()));
+    }
+
+    Ok(list)
+}
+
+/// Creates a reading list at the end of the user's lists.
+pub async fn insert_reading_list(pool: &PgPool, user_id: &str, list: InsertReadingList) -> AppResult<String> {
+    let name = validate_name(&list.name)?;
+    let list_id = Uuid::new_v4().hyphenated().to_string();
+
+    sqlx::query!(
+        r#"INSERT INTO reading_lists (id, user_id, name, is_public, position)
+        VALUES ($1, $2, $3, $4,
+            (SELECT COALESCE(MAX(position), 0) + 1 FROM reading_lists WHERE user_id = $2::varchar))"#,
+        list_id, user_id, name, list.is_public)
+        .execute(pool)
+        .await
+        .map_err(name_taken)?;
+
+    Ok(list_id)
+}
+
+/// Renames a reading list or changes its visibility.
+pub async fn update_reading_list(pool: &PgPool, list: &ReadingList, update: UpdateReadingList) -> AppResult<()> {
+    let name = match &update.name {
+        Some(name) => validate_name(name)?,
+        None => &list.name,
+    };
+
+    sqlx::query!(
+        r#"UPDATE reading_lists SET name = $1, is_public = $2 WHERE id = $3"#,
+        name, update.is_public.unwrap_or(list.is_public), list.id)
+        .execute(pool)
+        .await
+        .map_err(name_taken)?;
+
+    Ok(())
+}
+
+pub async fn delete_reading_list(pool: &PgPool, list_id: &str) -> AppResult<()> {
+    sqlx::query!(r#"DELETE FROM reading_lists WHERE id = $1"#, list_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Puts a user's reading lists in the order of `ids`, which must name each
+/// of their lists exactly once.
+pub async fn reorder_reading_lists(pool: &PgPool, user_id: &str, ids: &[String]) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+
+    let current = sqlx::query_scalar!(
+        r#"SELECT id FROM reading_lists WHERE user_id = $1 FOR UPDATE"#,
+        user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+    check_permutation(&current, ids, "reading list")?;
+
+    sqlx::query!(
+        r#"UPDATE reading_lists SET position = new_order.position::int
+        FROM unnest($1::varchar[]) WITH ORDINALITY AS new_order(id, position)
+        WHERE reading_lists.id = new_order.id"#,
+        ids)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Appends an article to a reading list. Adding it twice is a no-op.
+pub async fn add_list_article(pool: &PgPool, list: &ReadingList, article_id: &str) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+    lock_list(&mut tx, &list.id).await?;
+
+    let count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM reading_list_items WHERE list_id = $1"#,
+        list.id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if count >= MAX_LIST_ARTICLES {
+        return Err(AppError::Validation(format!("A reading list can hold at most {} articles", MAX_LIST_ARTICLES)));
+    }
+
+    sqlx::query!(
+        r#"INSERT INTO reading_list_items (list_id, article_id, position)
+        VALUES ($1, $2,
+            (SELECT COALESCE(MAX(position), 0) + 1 FROM reading_list_items WHERE list_id = $1::varchar))
+        ON CONFLICT DO NOTHING"#,
+        list.id, article_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn remove_list_article(pool: &PgPool, list: &ReadingList, article_id: &str) -> AppResult<()> {
+    sqlx::query!(
+        r#"DELETE FROM reading_list_items WHERE list_id = $1 AND article_id = $2"#,
+        list.id, article_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Puts the articles on a reading list in the order of `article_ids`, which
+/// must name each of them exactly once.
+pub async fn reorder_list_articles(pool: &PgPool, list: &ReadingList, article_ids: &[String]) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+    lock_list(&mut tx, &list.id).await?;
+
+    let current = sqlx::query_scalar!(
+        r#"SELECT article_id FROM reading_list_items WHERE list_id = $1"#,
+        list.id)
+        .fetch_all(&mut *tx)
+        .await?;
+    check_permutation(&current, article_ids, "article")?;
+
+    sqlx::query!(
+        r#"UPDATE reading_list_items SET position = new_order.position::int
+        FROM unnest($1::varchar[]) WITH ORDINALITY AS new_order(article_id, position)
+        WHERE reading_list_items.list_id = $2
+            AND reading_list_items.article_id = new_order.article_id"#,
+        article_ids, list.id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Serializes changes to the entries of one list, so concurrent additions
+/// cannot take the same position or overfill it.
+async fn lock_list(conn: &mut PgConnection, list_id: &str) -> AppResult<()> {
+    sqlx::query!(r#"SELECT id FROM reading_lists WHERE id = $1 FOR UPDATE"#, list_id)
+        .fetch_one(conn)
+        .await?;
+
+    Ok(())
+}
+
+fn check_permutation(current: &[String], ids: &[String], what: &str) -> AppResult<()> {
+    let current: HashSet<&str> = current.iter().map(String::as_str).collect();
+    let given: HashSet<&str> = ids.iter().map(String::as_str).collect();
+
+    if given.len() != ids.len() || given != current {
+        return Err(AppError::Validation(format!("The new order must list every {} exactly once", what)));
+    }
+
+    Ok(())
+}
+
+fn validate_name(name: &str) -> AppResult<&str> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err(AppError::Validation("Reading list name cannot be empty".to_string()));
+    }
+    if name.chars().count() > MAX_NAME_LENGTH {
+        return Err(AppError::Validation(format!("Reading list names can be at most {} characters long", MAX_NAME_LENGTH)));
+    }
+
+    Ok(name)
+}
+
+fn name_taken(error: sqlx::Error) -> AppError {
+    match error {
+        sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() =>
+            AppError::Conflict("You already have a reading list with this name".to_string()),
+        error => error.into(),
+    }
+}
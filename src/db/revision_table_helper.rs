@@ -0,0 +1,61 @@
+use uuid::Uuid;
+use sqlx::{ PgConnection, PgPool, Postgres, QueryBuilder };
+
+use crate::errors::{ AppError, AppResult };
+use crate::models::{ ArticleStatus, Revision };
+use crate::pagination::{ Cursor, Page, Paginated };
+
+/// Records the state of an article after a change made by `editor_id`, or
+/// by the server itself when it is `None`. Runs on the caller's connection
+/// so the revision is written in the same transaction as the change itself.
+pub async fn insert_revision(conn: &mut PgConnection, article_id: &str, editor_id: Option<&str>, title: &str, content: &str, content_html: &str, status: ArticleStatus) -> AppResult<String> {
+    let revision_id = Uuid::new_v4().hyphenated().to_string();
+
+    sqlx::query!(
+        r#"INSERT INTO article_revisions (id, article_id, editor_id, title, content, content_html, status)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)"#,
+        revision_id, article_id, editor_id, title, content, content_html, status as ArticleStatus)
+        .execute(conn)
+        .await?;
+
+    Ok(revision_id)
+}
+
+/// Lists the revisions of an article one page at a time.
+pub async fn list_revisions(pool: &PgPool, article_id: &str, page: &Page) -> AppResult<Paginated<Revision>> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        r#"SELECT article_revisions.id, article_id, editor_id, username as editor,
+        title, content, COALESCE(content_html, '') as content_html, status,
+        article_revisions.creation_date
+        FROM article_revisions
+        LEFT JOIN users ON article_revisions.editor_id = users.id
+        WHERE article_id = "#);
+    query.push_bind(article_id.to_string());
+
+    page.push_keyset(&mut query, "article_revisions.creation_date", "article_revisions.id");
+
+    let revisions = query
+        .build_query_as::<Revision>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(page.finish(revisions, |revision| Cursor {
+        timestamp: revision.creation_date,
+        id: revision.id.clone(),
+    }))
+}
+
+pub async fn get_revision(pool: &PgPool, article_id: &str, revision_id: &str) -> AppResult<Revision> {
+    sqlx::query_as!(
+        Revision,
+        r#"SELECT article_revisions.id, article_id, editor_id, username as "editor?",
+        title, content, COALESCE(content_html, '') as "content_html!",
+        status as "status: ArticleStatus", article_revisions.creation_date
+        FROM article_revisions
+        LEFT JOIN users ON article_revisions.editor_id = users.id
+        WHERE article_revisions.id = $1 AND article_id = $2"#,
+        revision_id, article_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Revision not found".to_string()))
+}
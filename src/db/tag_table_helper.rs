@@ -0,0 +1,116 @@
+use std::collections::HashSet;
+
+use uuid::Uuid;
+use sqlx::{ PgConnection, PgPool };
+
+use crate::errors::{ AppError, AppResult };
+use crate::models::{ Tag, TagCount };
+use crate::slug::slugify;
+
+pub const MAX_TAGS_PER_ARTICLE: usize = 10;
+const MAX_TAG_LENGTH: usize = 64;
+
+/// Normalizes tag names given by a client into tags, dropping duplicates.
+/// The slug identifies the tag; the name is kept for display.
+pub fn parse_tags(names: &[String]) -> AppResult<Vec<Tag>> {
+    let mut seen = HashSet::new();
+    let mut tags = Vec::new();
+
+    for name in names {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        let slug = slugify(&name);
+
+        if slug.is_empty() {
+            return Err(AppError::Validation(format!("Invalid tag: {:?}", name)));
+        }
+        if name.chars().count() > MAX_TAG_LENGTH || slug.len() > MAX_TAG_LENGTH {
+            return Err(AppError::Validation(format!("Tags can be at most {} characters long", MAX_TAG_LENGTH)));
+        }
+
+        if seen.insert(slug.clone()) {
+            tags.push(Tag { slug, name });
+        }
+    }
+
+    if tags.len() > MAX_TAGS_PER_ARTICLE {
+        return Err(AppError::Validation(format!("Articles can have at most {} tags", MAX_TAGS_PER_ARTICLE)));
+    }
+
+    Ok(tags)
+}
+
+/// Replaces the tags of an article, creating tags that do not exist yet.
+/// Existing tags keep the display name they were created with.
+pub async fn set_article_tags(conn: &mut PgConnection, article_id: &str, tags: &[Tag]) -> AppResult<()> {
+    let slugs: Vec<String> = tags.iter().map(|tag| tag.slug.clone()).collect();
+
+    for tag in tags {
+        sqlx::query!(
+            r#"INSERT INTO tags (id, slug, name) VALUES ($1, $2, $3)
+            ON CONFLICT (slug) DO NOTHING"#,
+            Uuid::new_v4().hyphenated().to_string(), tag.slug, tag.name)
+            .execute(&mut *conn)
+            .await?;
+    }
+
+    sqlx::query!(
+        r#"DELETE FROM article_tags WHERE article_id = $1"#,
+        article_id)
+        .execute(&mut *conn)
+        .await?;
+
+    sqlx::query!(
+        r#"INSERT INTO article_tags (article_id, tag_id)
+        SELECT $1, id FROM tags WHERE slug = ANY($2)"#,
+        article_id, &slugs)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Most used tags, counting published articles only.
+pub async fn popular_tags(pool: &PgPool, limit: i64) -> AppResult<Vec<TagCount>> {
+    let tags = sqlx::query_as!(
+        TagCount,
+        r#"SELECT tags.slug, tags.name, COUNT(*) as "article_count!"
+        FROM tags
+        INNER JOIN article_tags ON article_tags.tag_id = tags.id
+        INNER JOIN articles ON articles.id = article_tags.article_id
+        WHERE articles.status = 'published'
+        GROUP BY tags.id
+        ORDER BY COUNT(*) DESC, tags.slug
+        LIMIT $1"#,
+        limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(tags)
+}
+
+/// Tags whose slug starts with the slug of `prefix`, most used first.
+pub async fn autocomplete_tags(pool: &PgPool, prefix: &str, limit: i64) -> AppResult<Vec<TagCount>> {
+    let prefix = slugify(prefix);
+    if prefix.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // Slugs only contain letters, digits and dashes, so there is nothing to
+    // escape in the LIKE pattern.
+    let tags = sqlx::query_as!(
+        TagCount,
+        r#"SELECT tags.slug, tags.name, COUNT(articles.id) as "article_count!"
+        FROM tags
+        LEFT JOIN article_tags ON article_tags.tag_id = tags.id
+        LEFT JOIN articles ON articles.id = article_tags.article_id
+            AND articles.status = 'published'
+        WHERE tags.slug LIKE $1 || '%'
+        GROUP BY tags.id
+        ORDER BY COUNT(articles.id) DESC, tags.slug
+        LIMIT $2"#,
+        prefix, limit)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(tags)
+}
@@ -0,0 +1,128 @@
+use uuid::Uuid;
+use chrono::{ Duration, NaiveDateTime, Utc };
+use sqlx::PgPool;
+
+use crate::auth::{ self, REFRESH_TOKEN_TTL_DAYS };
+use crate::errors::{ AppError, AppResult };
+
+/// Stores a new refresh token for `user_id` and returns the plain token.
+/// Without a `family_id` the token starts a new family (a new login).
+pub async fn insert_refresh_token(pool: &PgPool, user_id: &str, family_id: Option<String>) -> AppResult<String> {
+    let token = auth::generate_refresh_token();
+    let token_id = Uuid::new_v4().hyphenated().to_string();
+    let family_id = family_id
+        .unwrap_or_else(|| Uuid::new_v4().hyphenated().to_string());
+    let expires_at = Utc::now().naive_utc() + Duration::days(REFRESH_TOKEN_TTL_DAYS);
+
+    sqlx::query!(
+        r#"INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4, $5)"#,
+        token_id, user_id, family_id, auth::hash_token(&token), expires_at)
+        .execute(pool)
+        .await?;
+
+    Ok(token)
+}
+
+/// Consumes a refresh token, returning the owner's id and the token family.
+/// Presenting a token that was already used revokes its whole family, since
+/// that means it has leaked.
+pub async fn consume_refresh_token(pool: &PgPool, token: &str) -> AppResult<(String, String)> {
+    let token_hash = auth::hash_token(token);
+
+    let consumed = sqlx::query!(
+        r#"UPDATE refresh_tokens SET revoked = true
+        WHERE token_hash = $1 AND revoked = false AND expires_at > $2
+        RETURNING user_id, family_id"#,
+        token_hash, Utc::now().naive_utc())
+        .fetch_optional(pool)
+        .await?;
+
+    if let Some(row) = consumed {
+        return Ok((row.user_id, row.family_id));
+    }
+
+    let reused = sqlx::query!(
+        "SELECT family_id FROM refresh_tokens WHERE token_hash = $1 AND revoked = true;",
+        token_hash)
+        .fetch_optional(pool)
+        .await?;
+
+    if let Some(row) = reused {
+        revoke_family(pool, &row.family_id).await?;
+    }
+
+    Err(AppError::Unauthorized("Invalid refresh token".to_string()))
+}
+
+/// Revokes the refresh token and every token rotated from the same login.
+pub async fn revoke_refresh_token(pool: &PgPool, token: &str) -> AppResult<()> {
+    let row = sqlx::query!(
+        "SELECT family_id FROM refresh_tokens WHERE token_hash = $1;",
+        auth::hash_token(token))
+        .fetch_optional(pool)
+        .await?;
+
+    match row {
+        Some(row) => revoke_family(pool, &row.family_id).await,
+        None => Err(AppError::Unauthorized("Invalid refresh token".to_string()))
+    }
+}
+
+/// Revokes every refresh token of a user, logging them out everywhere.
+pub async fn revoke_user_tokens(pool: &PgPool, user_id: &str) -> AppResult<()> {
+    sqlx::query!(
+        "UPDATE refresh_tokens SET revoked = true WHERE user_id = $1 AND revoked = false;",
+        user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+async fn revoke_family(pool: &PgPool, family_id: &str) -> AppResult<()> {
+    sqlx::query!(
+        "UPDATE refresh_tokens SET revoked = true WHERE family_id = $1;",
+        family_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Stores a single-use token for `purpose`, by its digest.
+/// Earlier unused tokens of the user for the same purpose stop working.
+pub async fn insert_email_token(pool: &PgPool, id: &str, user_id: &str, purpose: &str, token: &str, expires_at: NaiveDateTime) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"UPDATE email_tokens SET used = true WHERE user_id = $1 AND purpose = $2 AND used = false"#,
+        user_id, purpose)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(
+        r#"INSERT INTO email_tokens (id, user_id, purpose, token_hash, expires_at)
+        VALUES ($1, $2, $3, $4, $5)"#,
+        id, user_id, purpose, auth::hash_token(token), expires_at)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Marks a single-use token used and returns the id of its user. Fails
+/// when the token was already used or superseded, or has expired.
+pub async fn consume_email_token(pool: &PgPool, token: &str, purpose: &str) -> AppResult<String> {
+    let consumed = sqlx::query_scalar!(
+        r#"UPDATE email_tokens SET used = true
+        WHERE token_hash = $1 AND purpose = $2 AND used = false AND expires_at > $3
+        RETURNING user_id"#,
+        auth::hash_token(token), purpose, Utc::now().naive_utc())
+        .fetch_optional(pool)
+        .await?;
+
+    consumed.ok_or_else(|| AppError::Unauthorized("Invalid or expired token".to_string()))
+}
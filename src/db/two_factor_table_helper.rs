@@ -0,0 +1,172 @@
+use std::time::{ SystemTime, UNIX_EPOCH };
+
+use uuid::Uuid;
+use sqlx::PgPool;
+
+use crate::auth;
+use crate::errors::{ AppError, AppResult };
+use crate::models::SecondFactor;
+use crate::totp;
+
+/// A user's two-factor settings. `secret` is set from enrollment on, as
+/// stored, so encrypted; `enabled` once a first code has confirmed it.
+pub struct TwoFactor {
+    pub secret: Option<String>,
+    pub enabled: bool,
+}
+
+pub async fn get_two_factor(pool: &PgPool, user_id: &str) -> AppResult<TwoFactor> {
+    sqlx::query_as!(
+        TwoFactor,
+        r#"SELECT totp_secret as secret, totp_enabled as enabled FROM users WHERE id = $1"#,
+        user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))
+}
+
+/// Stores a new secret awaiting confirmation, replacing any earlier one.
+/// The secret is encrypted with `totp_key`, so the database alone is not
+/// enough to generate a user's codes.
+pub async fn set_pending_secret(pool: &PgPool, totp_key: &str, user_id: &str, secret: &str) -> AppResult<()> {
+    let result = sqlx::query!(
+        r#"UPDATE users SET totp_secret = $1, totp_last_step = NULL
+        WHERE id = $2 AND totp_enabled = false"#,
+        totp::encrypt_secret(totp_key, user_id, secret)?, user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::Conflict("Two-factor authentication is already enabled".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Turns two-factor authentication on once `code` matches the pending
+/// secret, and returns a fresh set of recovery codes. Only their SHA-256
+/// digests are stored. A fast hash is enough here: unlike a password, each
+/// code carries 80 random bits, which no one can guess from the digest.
+pub async fn enable_two_factor(pool: &PgPool, totp_key: &str, user_id: &str, code: &str) -> AppResult<Vec<String>> {
+    let two_factor = get_two_factor(pool, user_id).await?;
+
+    if two_factor.enabled {
+        return Err(AppError::Conflict("Two-factor authentication is already enabled".to_string()));
+    }
+    let secret = two_factor.secret
+        .ok_or_else(|| AppError::Conflict("Start enrollment before confirming it".to_string()))?;
+    let plain_secret = totp::decrypt_secret(totp_key, user_id, &secret)?;
+    let step = totp::matching_step(&plain_secret, code, unix_time())?
+        .ok_or_else(invalid_code)?;
+
+    let codes = totp::generate_recovery_codes();
+    let mut tx = pool.begin().await?;
+
+    let enabled = sqlx::query!(
+        r#"UPDATE users SET totp_enabled = true, totp_last_step = $1
+        WHERE id = $2 AND totp_secret = $3 AND totp_enabled = false"#,
+        step, user_id, secret)
+        .execute(&mut *tx)
+        .await?;
+    if enabled.rows_affected() == 0 {
+        return Err(AppError::Conflict("Two-factor enrollment changed, please start again".to_string()));
+    }
+
+    sqlx::query!(r#"DELETE FROM recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    for code in &codes {
+        sqlx::query!(
+            r#"INSERT INTO recovery_codes (id, user_id, code_hash) VALUES ($1, $2, $3)"#,
+            Uuid::new_v4().hyphenated().to_string(), user_id, auth::hash_token(code))
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(codes)
+}
+
+/// Turns two-factor authentication off and forgets the secret and the
+/// recovery codes.
+pub async fn disable_two_factor(pool: &PgPool, user_id: &str) -> AppResult<()> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        r#"UPDATE users SET totp_secret = NULL, totp_enabled = false, totp_last_step = NULL
+        WHERE id = $1"#,
+        user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    sqlx::query!(r#"DELETE FROM recovery_codes WHERE user_id = $1"#, user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+/// Checks the second factor of a user with two-factor authentication on: a
+/// TOTP code, which is accepted once, or an unused recovery code, which is
+/// used up.
+pub async fn check_second_factor(pool: &PgPool, totp_key: &str, user_id: &str, factor: &SecondFactor) -> AppResult<()> {
+    let two_factor = get_two_factor(pool, user_id).await?;
+    let secret = match two_factor.secret {
+        Some(secret) if two_factor.enabled => totp::decrypt_secret(totp_key, user_id, &secret)?,
+        _ => return Err(AppError::Conflict("Two-factor authentication is not enabled".to_string())),
+    };
+
+    let accepted = match (&factor.code, &factor.recovery_code) {
+        (Some(code), None) => match totp::matching_step(&secret, code, unix_time())? {
+            Some(step) => record_step(pool, user_id, step).await?,
+            None => false,
+        },
+        (None, Some(recovery_code)) => use_recovery_code(pool, user_id, recovery_code).await?,
+        _ => return Err(AppError::Validation("Send either code or recovery_code".to_string())),
+    };
+
+    if !accepted {
+        return Err(invalid_code());
+    }
+
+    Ok(())
+}
+
+/// Remembers the step of an accepted code. Fails for a step at or before
+/// the last one accepted, so a code cannot be replayed.
+async fn record_step(pool: &PgPool, user_id: &str, step: i64) -> AppResult<bool> {
+    let result = sqlx::query!(
+        r#"UPDATE users SET totp_last_step = $1
+        WHERE id = $2 AND (totp_last_step IS NULL OR totp_last_step < $1)"#,
+        step, user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+async fn use_recovery_code(pool: &PgPool, user_id: &str, code: &str) -> AppResult<bool> {
+    let result = sqlx::query!(
+        r#"UPDATE recovery_codes SET used = true
+        WHERE user_id = $1 AND code_hash = $2 AND used = false"#,
+        user_id, auth::hash_token(&totp::normalize_recovery_code(code)))
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() == 1)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+fn invalid_code() -> AppError {
+    AppError::Unauthorized("Invalid two-factor code".to_string())
+}
@@ -1,105 +1,175 @@
-use dotenv::dotenv;
 use uuid::Uuid;
-use std::env;
-use sqlx::{ Pool, Postgres, Result };
+use sqlx::{ PgPool, Postgres, QueryBuilder };
 use sqlx::postgres::PgQueryResult;
 
+use crate::errors::{ AppError, AppResult };
 use crate::models::{
     InsertUser, LoginUser,
-    SavedUser, UpdateUser, User,
-    ReturnArticle
+    PublicUser, SavedUser, UpdateUser, User,
+    UserFilters
 };
+use crate::pagination::{ Cursor, Page, Paginated };
 
-pub async fn fetch_all_users() -> Result<Vec<User>, sqlx::Error> {
-    let conn_pool = establish_connection().await;
+/// Lists full accounts, for administrators.
+pub async fn list_users(pool: &PgPool, filters: &UserFilters, page: &Page) -> AppResult<Paginated<User>> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        r#"SELECT id, first_name, last_name, username, email,
+            about, account_status, role, registration_date,
+            last_login_date FROM users WHERE true"#);
 
-    sqlx::query_as!(
-        User,
-        r#"
-        SELECT id, first_name, last_name, username, email,
-            about, account_status, registration_date,
-            last_login_date FROM users;
-            "#)
-        .fetch_all(&conn_pool)
-        .await
+    if let Some(status) = &filters.status {
+        query.push(" AND account_status = ");
+        query.push_bind(status.clone());
+    }
+
+    if let Some(role) = &filters.role {
+        query.push(" AND role = ");
+        query.push_bind(role.clone());
+    }
+
+    page.push_keyset(&mut query, "registration_date", "id");
+
+    let users = query
+        .build_query_as::<User>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(page.finish(users, |user| Cursor {
+        timestamp: user.registration_date,
+        id: user.id.clone(),
+    }))
 }
 
-pub async fn insert_user(user: InsertUser) -> Result<SavedUser, String> {
-    let pool = establish_connection().await;
+/// Lists active accounts as public profiles.
+pub async fn list_public_users(pool: &PgPool, page: &Page) -> AppResult<Paginated<PublicUser>> {
+    let mut query = QueryBuilder::<Postgres>::new(
+        r#"SELECT users.id, username, first_name || ' ' || last_name AS display_name,
+            about, registration_date,
+            (SELECT COUNT(*) FROM articles
+                WHERE articles.user_id = users.id AND status = 'published') AS article_count,
+            (SELECT COUNT(*) FROM follows WHERE followee_id = users.id) AS follower_count,
+            (SELECT COUNT(*) FROM follows WHERE follower_id = users.id) AS following_count
+            FROM users WHERE account_status = 'active'"#);
+
+    page.push_keyset(&mut query, "registration_date", "id");
+
+    let users = query
+        .build_query_as::<PublicUser>()
+        .fetch_all(pool)
+        .await?;
+
+    Ok(page.finish(users, |user| Cursor {
+        timestamp: user.registration_date,
+        id: user.id.clone(),
+    }))
+}
 
-    if is_email_taken(&pool, &user.email).await {
-        return Err("User with same email already exists".to_string());
+pub async fn get_public_user(pool: &PgPool, user_id: String) -> AppResult<PublicUser> {
+    sqlx::query_as!(
+        PublicUser,
+        r#"SELECT users.id, username, first_name || ' ' || last_name AS "display_name!",
+            about, registration_date,
+            (SELECT COUNT(*) FROM articles
+                WHERE articles.user_id = users.id AND status = 'published') AS "article_count!",
+            (SELECT COUNT(*) FROM follows WHERE followee_id = users.id) AS "follower_count!",
+            (SELECT COUNT(*) FROM follows WHERE follower_id = users.id) AS "following_count!"
+            FROM users WHERE id = $1 AND account_status = 'active';"#,
+        user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))
+}
+
+pub async fn insert_user(pool: &PgPool, user: InsertUser, bcrypt_cost: u32) -> AppResult<SavedUser> {
+    if is_email_taken(pool, &user.email).await? {
+        return Err(AppError::Conflict("User with same email already exists".to_string()));
     }
 
     let user_id = Uuid::new_v4().hyphenated().to_string();
 
-    let res = sqlx::query_as!(SavedUser, r#"
+    let saved_user = sqlx::query_as!(SavedUser, r#"
         INSERT INTO users (id, first_name, last_name, username, email,
         password) VALUES ($1, $2, $3, $4, $5, $6) RETURNING
         id, username, email, last_login_date; "#,
         user_id, user.first_name,
         user.last_name, user.username,
-        user.email, hash_password(user.password))
-        .fetch_one(&pool)
-        .await;
-    pool.close().await;
+        user.email, hash_password(user.password, bcrypt_cost)?)
+        .fetch_one(pool)
+        .await
+        .map_err(username_taken)?;
 
-    match res {
-        Ok(res) => Ok(res),
-        Err(e) => Err(e.to_string())
-    }
+    Ok(saved_user)
 }
 
-async fn is_email_taken(pool: &Pool<Postgres>, email: &str) -> bool {
-    sqlx::query!(
+async fn is_email_taken(pool: &PgPool, email: &str) -> AppResult<bool> {
+    let taken = sqlx::query!(
         "SELECT EXISTS(SELECT 1 FROM users WHERE email=$1);"
         , email)
         .fetch_one(pool)
-        .await
-        .expect("Error fetching record")
+        .await?
         .exists
-        .unwrap_or(false)
-}
+        .unwrap_or(false);
 
-pub async fn get_user_by_id(user_id: String) -> Option<User> {
-    let pool = establish_connection().await;
+    Ok(taken)
+}
 
+pub async fn get_user_by_id(pool: &PgPool, user_id: String) -> AppResult<User> {
     sqlx::query_as!(
         User,
         r#"SELECT id, first_name, last_name, username, email,
-        about, account_status, registration_date,
+        about, account_status, role, registration_date,
         last_login_date FROM users WHERE id=$1;"#,
         user_id)
-        .fetch_optional(&pool)
-        .await
-        .expect("Error fetching record")
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))
 }
 
-pub async fn get_user_info_by_credentials(login_user: LoginUser) -> Option<SavedUser> {
-    let pool = establish_connection().await;
-    let res = sqlx::query_as!(
+pub async fn find_user_by_email(pool: &PgPool, email: &str) -> AppResult<Option<User>> {
+    let user = sqlx::query_as!(
+        User,
+        r#"SELECT id, first_name, last_name, username, email,
+        about, account_status, role, registration_date,
+        last_login_date FROM users WHERE email=$1;"#,
+        email)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(user)
+}
+
+pub async fn get_user_info_by_credentials(pool: &PgPool, login_user: LoginUser) -> AppResult<SavedUser> {
+    let user = sqlx::query_as!(
         SavedUser,
         r#"SELECT id, username, email, last_login_date
         FROM users WHERE email=$1;"#,
         login_user.email)
-        .fetch_optional(&pool)
-        .await
-        .expect("Error fetching record");
+        .fetch_optional(pool)
+        .await?;
 
-    if let Some(user) = res {
-        let stored_password = get_password_from_db(&user.email, &pool).await;
+    if let Some(user) = user {
+        let stored_password = get_password_from_db(&user.email, pool).await?;
 
         if bcrypt::verify(&login_user.password, &stored_password).unwrap_or(false) {
-            return Some(user)
+            let (_, account_status) = get_role_and_status(pool, &user.id).await?;
+            if account_status == "suspended" {
+                return Err(AppError::Forbidden("Account is suspended".to_string()));
+            }
+            if account_status == "pending_verification" {
+                return Err(AppError::Forbidden("Email address is not verified".to_string()));
+            }
+
+            return Ok(user)
         }
     }
 
-    pool.close().await;
-    return None;
+    Err(AppError::Unauthorized("Invalid Credentials".to_string()))
 }
 
-pub async fn update_user(user: UpdateUser) -> Result<PgQueryResult, String> {
-    let pool = establish_connection().await;
+/// Updates a user's profile. A new `email` is only stored as pending until
+/// the address is confirmed with `apply_pending_email`; passwords change
+/// through `update_password`.
+pub async fn update_user(pool: &PgPool, user_id: String, user: UpdateUser) -> AppResult<PgQueryResult> {
     let mut update_query = String::from("UPDATE users SET");
     let mut params: Vec<String> = Vec::new();
     let mut param_index = 1;
@@ -107,7 +177,7 @@ pub async fn update_user(user: UpdateUser) -> Result<PgQueryResult, String> {
     if let Some(first_name) = user.first_name {
         update_query.push_str(" first_name = $");
         update_query.push_str(param_index.to_string().as_str());
-        update_query.push_str(",");
+        update_query.push(',');
         params.push(first_name);
         param_index += 1;
     }
@@ -115,19 +185,17 @@ pub async fn update_user(user: UpdateUser) -> Result<PgQueryResult, String> {
     if let Some(last_name) = user.last_name {
         update_query.push_str(" last_name = $");
         update_query.push_str(param_index.to_string().as_str());
-        update_query.push_str(",");
+        update_query.push(',');
         params.push(last_name);
         param_index += 1;
     }
 
     if let Some(email) = user.email {
-        if is_email_taken(&pool, &email).await {
-            return Err("User with same email already exists".to_string());
-        }
-
-        update_query.push_str(" email = $");
+        // Not checked against other accounts here, so the answer does not
+        // tell whether an address is registered; `apply_pending_email` does.
+        update_query.push_str(" pending_email = $");
         update_query.push_str(param_index.to_string().as_str());
-        update_query.push_str(",");
+        update_query.push(',');
         params.push(email);
         param_index += 1;
     }
@@ -135,7 +203,7 @@ pub async fn update_user(user: UpdateUser) -> Result<PgQueryResult, String> {
     if let Some(about) = user.about {
         update_query.push_str(" about = $");
         update_query.push_str(param_index.to_string().as_str());
-        update_query.push_str(",");
+        update_query.push(',');
         params.push(about);
         param_index += 1;
     }
@@ -143,24 +211,20 @@ pub async fn update_user(user: UpdateUser) -> Result<PgQueryResult, String> {
     if let Some(username) = user.username {
         update_query.push_str(" username = $");
         update_query.push_str(param_index.to_string().as_str());
-        update_query.push_str(",");
+        update_query.push(',');
         params.push(username);
         param_index += 1;
     }
 
-    if let Some(password) = user.password {
-        update_query.push_str(" password = $");
-        update_query.push_str(param_index.to_string().as_str());
-        update_query.push_str(",");
-        params.push(hash_password(password));
-        param_index += 1;
+    if params.is_empty() {
+        return Err(AppError::Validation("No fields to update".to_string()));
     }
     update_query.pop();
 
-    params.push(user.id);
+    params.push(user_id);
     update_query.push_str(" WHERE id = $");
     update_query.push_str(param_index.to_string().as_str());
-    update_query.push_str(";");
+    update_query.push(';');
 
     let update_query = update_query.as_str();
 
@@ -171,81 +235,140 @@ pub async fn update_user(user: UpdateUser) -> Result<PgQueryResult, String> {
     }
 
     let result = query
-        .execute(&pool)
-        .await;
+        .execute(pool)
+        .await
+        .map_err(username_taken)?;
 
-    pool.close().await;
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
 
-    match result {
-        Ok(res) => Ok(res),
-        Err(e) => Err(e.to_string())
+    Ok(result)
+}
+
+/// Makes the pending email of a user their address and returns it.
+pub async fn apply_pending_email(pool: &PgPool, user_id: &str) -> AppResult<String> {
+    let pending_email = sqlx::query_scalar!(
+        "SELECT pending_email FROM users WHERE id=$1;", user_id)
+        .fetch_optional(pool)
+        .await?
+        .flatten()
+        .ok_or_else(|| AppError::Conflict("No email change is pending".to_string()))?;
+
+    if is_email_taken(pool, &pending_email).await? {
+        return Err(AppError::Conflict("User with same email already exists".to_string()));
     }
+
+    sqlx::query!(
+        "UPDATE users SET email=pending_email, pending_email=NULL WHERE id=$1 AND pending_email=$2;",
+        user_id, pending_email)
+        .execute(pool)
+        .await?;
+
+    Ok(pending_email)
+}
+
+pub async fn update_password(pool: &PgPool, user_id: &str, password: String, bcrypt_cost: u32) -> AppResult<PgQueryResult> {
+    validate_password(&password)?;
+
+    let result = sqlx::query!(
+        "UPDATE users SET password=$1 WHERE id=$2;",
+        hash_password(password, bcrypt_cost)?, user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result)
 }
 
-async fn get_password_from_db(email: &str, pool: &Pool<Postgres>) -> String {
+/// Fails unless `password` is the user's current password.
+pub async fn check_password(pool: &PgPool, user_id: &str, password: &str) -> AppResult<()> {
+    let stored_password = sqlx::query_scalar!(
+        "SELECT password FROM users WHERE id=$1;", user_id)
+        .fetch_one(pool)
+        .await?;
+
+    if !bcrypt::verify(password, &stored_password).unwrap_or(false) {
+        return Err(AppError::Unauthorized("Current password is incorrect".to_string()));
+    }
+
+    Ok(())
+}
+
+async fn get_password_from_db(email: &str, pool: &PgPool) -> AppResult<String> {
     let row = sqlx::query!(
         "SELECT password FROM users WHERE email=$1;", email)
         .fetch_one(pool)
-        .await
-        .expect("Error decoding");
-
-    row.password
-}
-
-pub async fn get_latest_articles_by_user_id(id: String) -> Vec<ReturnArticle> {
-    let pool = establish_connection().await;
-    let result = sqlx::query_as!(
-        ReturnArticle,
-        r#"
-        SELECT articles.id, username as author, title,
-        users.id as user_id, content, status, creation_date
-        FROM articles
-        INNER JOIN users ON articles.user_id = users.id
-        WHERE user_id = $1
-        ORDER BY creation_date DESC
-        LIMIT 7;"#, id)
-        .fetch_all(&pool)
-        .await
-        .expect("Error retrieving records from table");
+        .await?;
 
-    pool.close().await;
-    result
+    Ok(row.password)
 }
 
-#[allow(dead_code)]
-pub async fn update_account_status(email: &str, status: &str) -> Result<PgQueryResult> {
-    let pool = establish_connection().await;
-    sqlx::query!("UPDATE users SET account_status=$1 WHERE email=$2;", status, email)
-        .execute(&pool)
-        .await
+pub async fn get_role_and_status(pool: &PgPool, user_id: &str) -> AppResult<(String, String)> {
+    let row = sqlx::query!(
+        "SELECT role, account_status FROM users WHERE id=$1;", user_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("User no longer exists".to_string()))?;
+
+    Ok((row.role, row.account_status))
 }
 
-pub async fn update_last_login_date(email: &str) -> Result<PgQueryResult> {
-    let pool = establish_connection().await;
-    sqlx::query!("UPDATE users SET last_login_date=now() WHERE email=$1;", email)
-        .execute(&pool)
-        .await
+pub async fn update_role(pool: &PgPool, user_id: &str, role: &str) -> AppResult<PgQueryResult> {
+    let result = sqlx::query!("UPDATE users SET role=$1 WHERE id=$2;", role, user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result)
 }
 
-#[allow(dead_code)]
-pub async fn delete_user(email: &str) -> Result<PgQueryResult> {
-    let pool = establish_connection().await;
-    sqlx::query!("DELETE FROM users WHERE email=$1;", email)
-        .execute(&pool)
+pub async fn update_account_status(pool: &PgPool, email: &str, status: &str) -> AppResult<PgQueryResult> {
+    let result = sqlx::query!("UPDATE users SET account_status=$1 WHERE email=$2;", status, email)
+        .execute(pool)
+        .await?;
+
+    Ok(result)
+}
+
+pub async fn update_last_login_date(pool: &PgPool, email: &str) -> AppResult<PgQueryResult> {
+    let result = sqlx::query!("UPDATE users SET last_login_date=now() WHERE email=$1;", email)
+        .execute(pool)
+        .await?;
+
+    Ok(result)
+}
+
+/// Deletes a user. Fails with a conflict while they still have articles,
+/// which are never deleted along with their author.
+pub async fn delete_user(pool: &PgPool, email: &str) -> AppResult<PgQueryResult> {
+    let result = sqlx::query!("DELETE FROM users WHERE email=$1;", email)
+        .execute(pool)
         .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(ref db_error) if db_error.constraint() == Some("articles_user_id_fkey") =>
+                AppError::Conflict("User still has articles".to_string()),
+            e => e.into(),
+        })?;
+
+    Ok(result)
 }
 
-fn hash_password(password: String) -> String {
-    bcrypt::hash(password, bcrypt::DEFAULT_COST)
-        .expect("Unable to hash")
+pub fn validate_password(password: &str) -> AppResult<()> {
+    if password.is_empty() {
+        return Err(AppError::Validation("Password cannot be empty".to_string()));
+    }
+
+    Ok(())
 }
 
-async fn establish_connection() -> Pool<Postgres> {
-    dotenv().ok();
-    let db_url = env::var("DATABASE_URL")
-        .expect("db url not found in .env");
+fn hash_password(password: String, cost: u32) -> AppResult<String> {
+    Ok(bcrypt::hash(password, cost)?)
+}
 
-    Pool::connect(&db_url)
-        .await
-        .expect("Error connecting to db")
+/// Usernames are unique regardless of case, see `users_username_idx`.
+fn username_taken(error: sqlx::Error) -> AppError {
+    match error {
+        sqlx::Error::Database(ref db_error) if db_error.constraint() == Some("users_username_idx") =>
+            AppError::Conflict("Username is already taken".to_string()),
+        error => error.into(),
+    }
 }
@@ -0,0 +1,116 @@
+use serde::Serialize;
+use similar::{ ChangeTag, TextDiff };
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// One line of a diff. Line numbers are 1-based and missing on the side
+/// the line does not exist in.
+#[derive(Debug, Serialize)]
+pub struct LineChange {
+    pub op: LineOp,
+    pub old_line: Option<usize>,
+    pub new_line: Option<usize>,
+    pub text: String,
+}
+
+/// Line-level diff turning `old` into `new`.
+pub fn line_diff(old: &str, new: &str) -> Vec<LineChange> {
+    // A missing final newline should not make the last line look changed.
+    let old = terminated(old);
+    let new = terminated(new);
+
+    TextDiff::from_lines(&old, &new)
+        .iter_all_changes()
+        .map(|change| LineChange {
+            op: match change.tag() {
+                ChangeTag::Equal => LineOp::Equal,
+                ChangeTag::Insert => LineOp::Insert,
+                ChangeTag::Delete => LineOp::Delete,
+            },
+            old_line: change.old_index().map(|index| index + 1),
+            new_line: change.new_index().map(|index| index + 1),
+            text: change.value().trim_end_matches(['\r', '\n']).to_string(),
+        })
+        .collect()
+}
+
+fn terminated(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(changes: &[LineChange]) -> Vec<(&'static str, Option<usize>, Option<usize>, &str)> {
+        changes
+            .iter()
+            .map(|change| {
+                let op = match change.op {
+                    LineOp::Equal => "=",
+                    LineOp::Insert => "+",
+                    LineOp::Delete => "-",
+                };
+                (op, change.old_line, change.new_line, change.text.as_str())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_texts_are_all_equal() {
+        assert_eq!(
+            ops(&line_diff("a\nb\n", "a\nb\n")),
+            vec![("=", Some(1), Some(1), "a"), ("=", Some(2), Some(2), "b")]);
+    }
+
+    #[test]
+    fn changed_line_is_a_delete_and_an_insert() {
+        assert_eq!(
+            ops(&line_diff("a\nb\nc\n", "a\nB\nc\n")),
+            vec![
+                ("=", Some(1), Some(1), "a"),
+                ("-", Some(2), None, "b"),
+                ("+", None, Some(2), "B"),
+                ("=", Some(3), Some(3), "c"),
+            ]);
+    }
+
+    #[test]
+    fn line_numbers_follow_each_side() {
+        assert_eq!(
+            ops(&line_diff("a\nc\n", "new\na\nb\nc\n")),
+            vec![
+                ("+", None, Some(1), "new"),
+                ("=", Some(1), Some(2), "a"),
+                ("+", None, Some(3), "b"),
+                ("=", Some(2), Some(4), "c"),
+            ]);
+    }
+
+    #[test]
+    fn missing_final_newline_is_not_a_change() {
+        assert!(line_diff("a\nb", "a\nb\n").iter().all(|change| matches!(change.op, LineOp::Equal)));
+    }
+
+    #[test]
+    fn line_endings_are_stripped() {
+        assert_eq!(ops(&line_diff("a\r\n", "a\r\n")), vec![("=", Some(1), Some(1), "a")]);
+    }
+
+    #[test]
+    fn empty_texts() {
+        assert!(line_diff("", "").is_empty());
+        assert_eq!(ops(&line_diff("", "a")), vec![("+", None, Some(1), "a")]);
+        assert_eq!(ops(&line_diff("a", "")), vec![("-", Some(1), None, "a")]);
+    }
+}
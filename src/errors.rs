@@ -0,0 +1,102 @@
+use std::fmt;
+
+use actix_web::{ http::StatusCode, HttpResponse, ResponseError };
+use serde_json::json;
+
+/// Every failure a helper or route can produce. Each variant maps to one
+/// HTTP status and a stable `code` that clients can match on.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Conflict(String),
+    Validation(String),
+    Database(sqlx::Error),
+    Internal(String),
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Conflict(_) => "conflict",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Database(_) => "database_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::Conflict(message)
+            | AppError::Validation(message)
+            | AppError::Internal(message) => write!(f, "{}", message),
+            AppError::Database(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // Database and internal errors are logged but never echoed back,
+        // they can contain query text or connection details.
+        let message = match self {
+            AppError::Database(_) | AppError::Internal(_) => {
+                log::error!("{}", self);
+                "Something went wrong".to_string()
+            },
+            _ => self.to_string(),
+        };
+
+        HttpResponse::build(self.status_code())
+            .json(json!({
+                "status": "failed",
+                "code": self.code(),
+                "message": message
+            }))
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::RowNotFound => AppError::NotFound("Record not found".to_string()),
+            sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() =>
+                AppError::Conflict("Record already exists".to_string()),
+            e => AppError::Database(e),
+        }
+    }
+}
+
+impl From<bcrypt::BcryptError> for AppError {
+    fn from(e: bcrypt::BcryptError) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
+
+impl From<jsonwebtoken::errors::Error> for AppError {
+    fn from(e: jsonwebtoken::errors::Error) -> Self {
+        AppError::Internal(e.to_string())
+    }
+}
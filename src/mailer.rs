@@ -0,0 +1,124 @@
+use std::fs;
+use std::sync::Arc;
+
+use actix_web::web;
+use lettre::{ FileTransport, Message, SmtpTransport, Transport };
+use lettre::message::{ header::ContentType, Mailbox };
+use lettre::transport::smtp::authentication::Credentials;
+
+use crate::config::MailConfig;
+use crate::errors::{ AppError, AppResult };
+
+/// A plain-text email to a single recipient.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers emails. Implementations may block; use `deliver` from request
+/// handlers.
+pub trait Mailer: Send + Sync {
+    fn send(&self, email: &Email) -> AppResult<()>;
+}
+
+/// Sends through an SMTP relay, upgrading the connection with STARTTLS.
+pub struct SmtpMailer {
+    from: Mailbox,
+    transport: SmtpTransport,
+}
+
+/// Writes each email to a `.eml` file in a directory instead of sending
+/// it, for local development and tests.
+pub struct OutboxMailer {
+    from: Mailbox,
+    transport: FileTransport,
+}
+
+impl SmtpMailer {
+    pub fn new(config: &MailConfig) -> AppResult<SmtpMailer> {
+        let mut builder = SmtpTransport::starttls_relay(&config.smtp_host)
+            .map_err(|e| AppError::Internal(format!("Invalid SMTP relay: {}", e)))?
+            .port(config.smtp_port);
+
+        if !config.smtp_username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                config.smtp_username.clone(),
+                config.smtp_password.clone()));
+        }
+
+        Ok(SmtpMailer {
+            from: sender(config)?,
+            transport: builder.build(),
+        })
+    }
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, email: &Email) -> AppResult<()> {
+        self.transport
+            .send(&message(&self.from, email)?)
+            .map_err(|e| AppError::Internal(format!("Failed to send email: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+impl OutboxMailer {
+    pub fn new(config: &MailConfig) -> AppResult<OutboxMailer> {
+        fs::create_dir_all(&config.outbox_dir)
+            .map_err(|e| AppError::Internal(format!("Cannot create {}: {}", config.outbox_dir, e)))?;
+
+        Ok(OutboxMailer {
+            from: sender(config)?,
+            transport: FileTransport::new(&config.outbox_dir),
+        })
+    }
+}
+
+impl Mailer for OutboxMailer {
+    fn send(&self, email: &Email) -> AppResult<()> {
+        self.transport
+            .send(&message(&self.from, email)?)
+            .map_err(|e| AppError::Internal(format!("Failed to write email: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+/// Builds the mailer selected by `config.transport`.
+pub fn from_config(config: &MailConfig) -> AppResult<Arc<dyn Mailer>> {
+    match config.transport.as_str() {
+        "smtp" => Ok(Arc::new(SmtpMailer::new(config)?)),
+        "outbox" => Ok(Arc::new(OutboxMailer::new(config)?)),
+        transport => Err(AppError::Internal(format!("Unknown mail transport: {}", transport))),
+    }
+}
+
+/// Sends an email on the blocking thread pool.
+pub async fn deliver(mailer: web::Data<dyn Mailer>, email: Email) -> AppResult<()> {
+    web::block(move || mailer.send(&email))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+}
+
+fn sender(config: &MailConfig) -> AppResult<Mailbox> {
+    config.from
+        .parse()
+        .map_err(|_| AppError::Internal(format!("Invalid sender address: {}", config.from)))
+}
+
+fn message(from: &Mailbox, email: &Email) -> AppResult<Message> {
+    let to: Mailbox = email.to
+        .parse()
+        .map_err(|_| AppError::Validation(format!("Invalid email address: {}", email.to)))?;
+
+    Message::builder()
+        .from(from.clone())
+        .to(to)
+        .subject(email.subject.clone())
+        .header(ContentType::TEXT_PLAIN)
+        .body(email.body.clone())
+        .map_err(|e| AppError::Internal(format!("Cannot build email: {}", e)))
+}
@@ -1,16 +1,36 @@
 use actix_web::{
-    get, middleware::Logger,
+    get, middleware::Logger, web,
     App, HttpResponse, HttpServer,
     Responder
 };
 use serde_json::json;
-use std::env;
+use sqlx::PgPool;
+use std::{ env, process };
+use std::time::Duration;
 
+mod auth;
+mod cli;
+mod config;
+mod cors;
+mod diff;
+mod errors;
+mod mailer;
+mod markdown;
 mod models;
+mod pagination;
+mod permissions;
+mod publisher;
 mod routes;
+mod search;
+mod slug;
+mod totp;
 mod db;
 
-use crate::routes::{ user_routes, article_routes };
+use crate::cli::{ Command, MigrateCommand };
+use crate::config::Config;
+use crate::db::{ article_table_helper, migrations };
+use crate::errors::AppError;
+use crate::routes::{ admin_routes, user_routes, article_routes, comment_routes, reading_list_routes, tag_routes };
 
 #[get("/")]
 async fn index() -> impl Responder {
@@ -21,24 +41,129 @@ async fn index() -> impl Responder {
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let command = cli::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, cli::USAGE);
+        process::exit(2);
+    });
+
+    if command == Command::Help {
+        println!("{}", cli::USAGE);
+        return Ok(());
+    }
+
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        process::exit(1);
+    });
 
     if env::var_os("RUST_LOG").is_none() {
-        env::set_var("RUST_LOG", "actix_web=info");
+        env::set_var("RUST_LOG", &config.log_level);
     }
 
     env_logger::init();
-    println!("Server started Successfully");
+
+    let pool = db::create_pool(&config).await.unwrap_or_else(|e| {
+        eprintln!("Failed to connect to Postgres: {}", e);
+        process::exit(1);
+    });
+
+    match command {
+        Command::Migrate(command) => migrate(&pool, command).await,
+        _ => serve(pool, config).await,
+    }
+}
+
+async fn serve(pool: PgPool, config: Config) -> std::io::Result<()> {
+    if let Err(e) = config.require_totp_key() {
+        eprintln!("Invalid configuration: {}", e);
+        process::exit(1);
+    }
+
+    if config.auto_migrate {
+        if let Err(e) = migrations::run_pending(&pool).await {
+            eprintln!("Failed to apply migrations: {}", e);
+            process::exit(1);
+        }
+    }
+
+    let mailer = mailer::from_config(&config.mail).unwrap_or_else(|e| {
+        eprintln!("Failed to set up mail delivery: {}", e);
+        process::exit(1);
+    });
+
+    render_missing_html(&pool).await;
+    publisher::spawn(pool.clone(), Duration::from_secs(config.publish_interval));
+
+    let bind_address = config.bind_address();
+    println!("Server started Successfully on {}:{}", bind_address.0, bind_address.1);
 
     HttpServer::new(move || {
         App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::from(mailer.clone()))
+            .app_data(web::JsonConfig::default().error_handler(|err, _| {
+                AppError::Validation(err.to_string()).into()
+            }))
+            .app_data(web::QueryConfig::default().error_handler(|err, _| {
+                AppError::Validation(err.to_string()).into()
+            }))
             .configure(user_routes::user_scopes)
             .configure(article_routes::article_scopes)
+            .configure(admin_routes::admin_scopes)
+            .configure(tag_routes::tag_scopes)
+            .configure(comment_routes::comment_scopes)
+            .configure(reading_list_routes::reading_list_scopes)
             .service(index)
+            .wrap(cors::from_config(&config.cors_origins))
             .wrap(Logger::default())
     })
-    .bind("192.168.185.216:4000")?
+    .bind(bind_address)?
         .run()
         .await?;
 
     Ok(())
 }
+
+async fn migrate(pool: &PgPool, command: MigrateCommand) -> std::io::Result<()> {
+    let result = match command {
+        MigrateCommand::Up => match migrations::run_pending(pool).await {
+            Ok(()) => {
+                render_missing_html(pool).await;
+                println!("Database is up to date");
+                Ok(())
+            },
+            Err(e) => Err(e),
+        },
+        MigrateCommand::Down => migrations::revert_last(pool)
+            .await
+            .map(|reverted| match reverted {
+                Some(version) => println!("Reverted migration {}", version),
+                None => println!("No applied migrations to revert"),
+            }),
+        MigrateCommand::Status => migrations::status(pool)
+            .await
+            .map(|statuses| {
+                for status in statuses {
+                    let state = if status.applied { "applied" } else { "pending" };
+                    println!("{:<8} {} {}", state, status.version, status.description);
+                }
+            }),
+    };
+
+    if let Err(e) = result {
+        eprintln!("Migration failed: {}", e);
+        process::exit(1);
+    }
+
+    Ok(())
+}
+
+/// Fills in rendered HTML for content saved before rendering existed.
+async fn render_missing_html(pool: &PgPool) {
+    match article_table_helper::render_missing_html(pool).await {
+        Ok(0) => {},
+        Ok(count) => log::info!("Rendered HTML for {} articles and revisions", count),
+        Err(e) => log::error!("Failed to render article HTML: {}", e),
+    }
+}
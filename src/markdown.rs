@@ -0,0 +1,265 @@
+use std::collections::{ HashMap, HashSet };
+
+use ammonia::Builder;
+use pulldown_cmark::{ html, Event, Options, Parser, Tag, TagEnd };
+use serde::{ Serialize, Deserialize };
+
+use crate::search;
+use crate::slug::slugify;
+
+/// A heading of an article, linked by the `id` given to it in the HTML.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub id: String,
+    pub text: String,
+}
+
+/// Longest excerpt, in characters, before the ellipsis.
+const EXCERPT_LENGTH: usize = 200;
+/// Reading speed used for `reading_time`.
+const WORDS_PER_MINUTE: usize = 200;
+
+pub struct Rendered {
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+    /// The whole article as plain text, for search snippets.
+    pub text: String,
+    /// Start of the body text as plain text, without headings or code.
+    pub excerpt: String,
+    pub word_count: i32,
+    /// Estimated minutes to read the article, at least 1 unless it is empty.
+    pub reading_time: i32,
+}
+
+/// Renders article Markdown (CommonMark with GFM tables, footnotes and
+/// strikethrough) into sanitized HTML, collects its headings into a table
+/// of contents and works out the excerpt, word count and reading time.
+pub fn render(markdown: &str) -> Rendered {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_FOOTNOTES
+        | Options::ENABLE_STRIKETHROUGH;
+    let mut events: Vec<Event> = Parser::new_ext(markdown, options).collect();
+    let toc = anchor_headings(&mut events);
+    let (body, word_count) = plain_text(&events);
+    let text = text_of(&events);
+
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, events.into_iter());
+
+    Rendered {
+        html: sanitizer().clean(&unsafe_html).to_string(),
+        toc,
+        text,
+        excerpt: excerpt_of(&body),
+        word_count: word_count as i32,
+        reading_time: word_count.div_ceil(WORDS_PER_MINUTE) as i32,
+    }
+}
+
+/// Returns the body text outside headings and code blocks, and the number
+/// of words in the whole article.
+fn plain_text(events: &[Event]) -> (String, usize) {
+    let mut body = String::new();
+    let mut word_count = 0;
+    let mut skipped = 0;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading { .. } | Tag::CodeBlock(_)) => skipped += 1,
+            Event::End(TagEnd::Heading(_) | TagEnd::CodeBlock) => skipped -= 1,
+            Event::Text(text) | Event::Code(text) => {
+                word_count += text.split_whitespace().count();
+                if skipped == 0 {
+                    body.push_str(text);
+                }
+            },
+            Event::SoftBreak | Event::HardBreak | Event::End(_) if skipped == 0 => body.push(' '),
+            _ => {}
+        }
+    }
+
+    (body, word_count)
+}
+
+/// Returns every piece of text in the article, headings and code included.
+/// Raw HTML is left out, and the search highlight markers are replaced so
+/// they can only come from `ts_headline`.
+fn text_of(events: &[Event]) -> String {
+    let mut text = String::new();
+
+    for event in events {
+        match event {
+            Event::Text(part) | Event::Code(part) => text.extend(part.chars().map(|c| {
+                if search::is_highlight_marker(c) { ' ' } else { c }
+            })),
+            Event::SoftBreak | Event::HardBreak | Event::End(_) => text.push(' '),
+            _ => {}
+        }
+    }
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Cuts `text` down to `EXCERPT_LENGTH` characters at a word boundary.
+fn excerpt_of(text: &str) -> String {
+    let mut excerpt = String::new();
+
+    for word in text.split_whitespace() {
+        if excerpt.chars().count() + word.chars().count() + 1 > EXCERPT_LENGTH {
+            if excerpt.is_empty() {
+                excerpt.extend(word.chars().take(EXCERPT_LENGTH));
+            }
+            excerpt.push('…');
+            return excerpt;
+        }
+        if !excerpt.is_empty() {
+            excerpt.push(' ');
+        }
+        excerpt.push_str(word);
+    }
+
+    excerpt
+}
+
+/// Gives every heading an `id` made from its text, unique within the
+/// article, and returns the headings in order.
+fn anchor_headings(events: &mut [Event]) -> Vec<TocEntry> {
+    let mut toc = Vec::new();
+    let mut used = HashSet::new();
+
+    for start in 0..events.len() {
+        let level = match &events[start] {
+            Event::Start(Tag::Heading { level, .. }) => *level as u8,
+            _ => continue,
+        };
+
+        let text: String = events[start + 1..]
+            .iter()
+            .take_while(|event| !matches!(event, Event::End(TagEnd::Heading(_))))
+            .filter_map(|event| match event {
+                Event::Text(text) | Event::Code(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+
+        let base = match slugify(&text) {
+            slug if slug.is_empty() => "section".to_string(),
+            slug => slug,
+        };
+        let anchor = (1..)
+            .map(|n| if n == 1 { base.clone() } else { format!("{}-{}", base, n) })
+            .find(|candidate| used.insert(candidate.clone()))
+            .expect("a free anchor always exists");
+
+        if let Event::Start(Tag::Heading { id, .. }) = &mut events[start] {
+            *id = Some(anchor.clone().into());
+        }
+
+        toc.push(TocEntry { level, id: anchor, text: text.trim().to_string() });
+    }
+
+    toc
+}
+
+/// Ammonia's default allowlist plus what the Markdown renderer needs:
+/// heading anchors and footnotes.
+fn sanitizer() -> Builder<'static> {
+    let mut builder = Builder::default();
+
+    for tag in ["h1", "h2", "h3", "h4", "h5", "h6", "div"] {
+        builder.add_tag_attributes(tag, ["id"]);
+    }
+
+    builder.allowed_classes(HashMap::from([
+        ("sup", HashSet::from(["footnote-reference", "footnote-definition-label"])),
+        ("div", HashSet::from(["footnote-definition"])),
+    ]));
+
+    builder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(toc: &[TocEntry]) -> Vec<&str> {
+        toc.iter().map(|entry| entry.id.as_str()).collect()
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed() {
+        let html = render("Hi <script>alert(1)</script>\n\n<img src=\"x.png\" onerror=\"alert(1)\">").html;
+
+        assert!(!html.contains("<script"), "{}", html);
+        assert!(!html.contains("onerror"), "{}", html);
+        assert!(html.contains("<img src=\"x.png\">"), "{}", html);
+    }
+
+    #[test]
+    fn javascript_links_are_removed() {
+        let html = render("[click](javascript:alert(1)) <a href=\"javascript:alert(1)\">raw</a>").html;
+
+        assert!(!html.contains("javascript:"), "{}", html);
+    }
+
+    #[test]
+    fn headings_and_footnotes_keep_their_attributes() {
+        let html = render("# Title\n\nText[^1]\n\n[^1]: Note").html;
+
+        assert!(html.contains("<h1 id=\"title\">Title</h1>"), "{}", html);
+        assert!(html.contains("class=\"footnote-reference\""), "{}", html);
+        assert!(html.contains("class=\"footnote-definition\""), "{}", html);
+    }
+
+    #[test]
+    fn other_classes_and_ids_are_dropped() {
+        let html = render("<p class=\"evil\" id=\"login\">x</p>").html;
+
+        assert!(!html.contains("evil"), "{}", html);
+        assert!(!html.contains("login"), "{}", html);
+    }
+
+    #[test]
+    fn toc_ids_are_unique() {
+        let rendered = render("# Intro\n\n## Intro\n\n## Intro 2\n\n### `Code` *span*\n\n## !!!\n\n## ???");
+
+        assert_eq!(ids(&rendered.toc), ["intro", "intro-2", "intro-2-2", "code-span", "section", "section-2"]);
+        assert_eq!(rendered.toc[3].text, "Code span");
+        assert_eq!(rendered.toc[1].level, 2);
+        assert!(rendered.html.contains("<h2 id=\"intro-2-2\">"), "{}", rendered.html);
+    }
+
+    #[test]
+    fn text_leaves_out_raw_html_and_highlight_markers() {
+        let text = render("# Title\n\nSome *text* <b>bold</b>\n\n    code \u{2}x\u{3}").text;
+
+        assert_eq!(text, "Title Some text bold code x");
+    }
+
+    #[test]
+    fn excerpt_skips_headings_and_code() {
+        let rendered = render("# Heading\n\nFirst paragraph.\n\n```\nlet x = 1;\n```\n\nSecond one.");
+
+        assert_eq!(rendered.excerpt, "First paragraph. Second one.");
+        assert_eq!(rendered.word_count, 9);
+        assert_eq!(rendered.reading_time, 1);
+    }
+
+    #[test]
+    fn long_excerpts_end_with_an_ellipsis() {
+        let excerpt = render(&"word ".repeat(100)).excerpt;
+
+        assert!(excerpt.ends_with('…'));
+        assert!(excerpt.chars().count() <= EXCERPT_LENGTH + 1);
+    }
+
+    #[test]
+    fn empty_article() {
+        let rendered = render("");
+
+        assert_eq!(rendered.html, "");
+        assert_eq!(rendered.word_count, 0);
+        assert_eq!(rendered.reading_time, 0);
+    }
+}
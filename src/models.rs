@@ -1,6 +1,14 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
 use sqlx::FromRow;
+use sqlx::types::Json;
 use serde::{ Serialize, Deserialize };
-use chrono::NaiveDateTime;
+use chrono::{ DateTime, NaiveDateTime, Utc };
+
+use crate::errors::AppError;
+use crate::markdown::TocEntry;
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct User {
@@ -11,6 +19,7 @@ pub struct User {
     pub email: String,
     pub about: String,
     pub account_status: String,
+    pub role: String,
     pub registration_date: NaiveDateTime,
     pub last_login_date: NaiveDateTime
 }
@@ -24,6 +33,29 @@ pub struct InsertUser {
     pub password: String,
 }
 
+/// What anyone may see about a user. Email, account status and login
+/// times stay in `User`, which only the account owner receives.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct PublicUser {
+    pub id: String,
+    pub username: String,
+    pub display_name: String,
+    pub about: String,
+    pub registration_date: NaiveDateTime,
+    pub article_count: i64,
+    pub follower_count: i64,
+    pub following_count: i64,
+}
+
+/// A user in a followers or following list, with when the follow started.
+#[derive(Debug, Serialize, FromRow)]
+pub struct Follow {
+    pub id: String,
+    pub username: String,
+    pub display_name: String,
+    pub followed_at: NaiveDateTime,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginUser {
     pub email: String,
@@ -39,38 +71,236 @@ pub struct SavedUser {
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct AuthTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String,
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshToken {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmail {
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailAddress {
+    pub email: String,
+}
+
+/// The second step of a login, or another action guarded by two-factor
+/// authentication: either a TOTP `code` or a `recovery_code`.
+#[derive(Debug, Deserialize)]
+pub struct SecondFactor {
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CompleteLogin {
+    pub challenge: String,
+    #[serde(flatten)]
+    pub factor: SecondFactor,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePassword {
+    pub current_password: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPassword {
+    pub token: String,
+    pub password: String,
+}
+
+/// Profile fields a user may change. Unknown fields are refused, so a
+/// `password` sent here fails instead of being dropped silently.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct UpdateUser {
-    pub id: String,
     pub first_name: Option<String>,
     pub last_name: Option<String>,
     pub username: Option<String>,
-    pub password: Option<String>,
     pub email: Option<String>,
     pub about: Option<String>
 }
 
+/// Lifecycle of an article, stored as the `article_status` Postgres enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "article_status", rename_all = "snake_case")]
+pub enum ArticleStatus {
+    Draft,
+    InReview,
+    Scheduled,
+    Published,
+    Unlisted,
+    Archived,
+}
+
+impl ArticleStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ArticleStatus::Draft => "draft",
+            ArticleStatus::InReview => "in_review",
+            ArticleStatus::Scheduled => "scheduled",
+            ArticleStatus::Published => "published",
+            ArticleStatus::Unlisted => "unlisted",
+            ArticleStatus::Archived => "archived",
+        }
+    }
+
+    /// Statuses an article may be created with.
+    pub fn is_initial(self) -> bool {
+        !matches!(self, ArticleStatus::Archived)
+    }
+
+    /// Transitions an author may make on their own article. See
+    /// `editor_can_transition_to` for editors and admins.
+    pub fn can_transition_to(self, next: ArticleStatus) -> bool {
+        use ArticleStatus::*;
+
+        if self == next {
+            return true;
+        }
+
+        match self {
+            Draft => matches!(next, InReview | Scheduled | Published | Unlisted | Archived),
+            InReview => matches!(next, Draft | Scheduled | Published | Unlisted | Archived),
+            Scheduled => matches!(next, Draft | Published | Archived),
+            Published => matches!(next, Unlisted | Archived),
+            Unlisted => matches!(next, Published | Archived),
+            Archived => matches!(next, Published | Unlisted),
+        }
+    }
+
+    /// Transitions an editor or admin may make on any article: the author's,
+    /// plus sending a scheduled article to review and a published, unlisted
+    /// or archived one back to draft or review.
+    pub fn editor_can_transition_to(self, next: ArticleStatus) -> bool {
+        use ArticleStatus::*;
+
+        self.can_transition_to(next) || match self {
+            Draft | InReview => false,
+            Scheduled => next == InReview,
+            Published | Unlisted | Archived => matches!(next, Draft | InReview),
+        }
+    }
+
+    /// Whether anyone holding the article id may read it.
+    pub fn is_public(self) -> bool {
+        matches!(self, ArticleStatus::Published | ArticleStatus::Unlisted)
+    }
+}
+
+impl fmt::Display for ArticleStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ArticleStatus {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "draft" => Ok(ArticleStatus::Draft),
+            "in_review" => Ok(ArticleStatus::InReview),
+            "scheduled" => Ok(ArticleStatus::Scheduled),
+            "published" => Ok(ArticleStatus::Published),
+            "unlisted" => Ok(ArticleStatus::Unlisted),
+            "archived" => Ok(ArticleStatus::Archived),
+            _ => Err(AppError::Validation(format!("Unknown article status: {}", value))),
+        }
+    }
+}
+
+/// Stored as the `reaction_kind` Postgres enum.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "reaction_kind", rename_all = "snake_case")]
+pub enum ReactionKind {
+    Like,
+    Insightful,
+    Funny,
+    Love,
+    Celebrate,
+}
+
+impl ReactionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReactionKind::Like => "like",
+            ReactionKind::Insightful => "insightful",
+            ReactionKind::Funny => "funny",
+            ReactionKind::Love => "love",
+            ReactionKind::Celebrate => "celebrate",
+        }
+    }
+}
+
+impl FromStr for ReactionKind {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "like" => Ok(ReactionKind::Like),
+            "insightful" => Ok(ReactionKind::Insightful),
+            "funny" => Ok(ReactionKind::Funny),
+            "love" => Ok(ReactionKind::Love),
+            "celebrate" => Ok(ReactionKind::Celebrate),
+            _ => Err(AppError::Validation(format!("Unknown reaction: {}", value))),
+        }
+    }
+}
+
+/// Someone who reacted to an article.
+#[derive(Debug, Serialize, FromRow)]
+pub struct Reaction {
+    pub user_id: String,
+    pub username: String,
+    pub kind: ReactionKind,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ReactionFilters {
+    pub kind: Option<ReactionKind>,
+}
+
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct Article {
     pub id: String,
     pub user_id: String,
     pub title: String,
+    pub slug: String,
     pub content: String,
-    pub status: String,
+    pub status: ArticleStatus,
     pub creation_date: NaiveDateTime,
+    pub publish_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
 pub struct InsertArticle {
-    pub user_id: String,
     pub title: String,
     pub content: String,
-    pub status: Option<String>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UpdateArticleStatus {
-    pub id: String,
-    pub status: String,
+    pub status: Option<ArticleStatus>,
+    /// Publishes the article at this time. Implies `scheduled`.
+    pub publish_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -78,7 +308,11 @@ pub struct UpdateArticle {
     pub id: String,
     pub title: Option<String>,
     pub content: Option<String>,
-    pub status: Option<String>,
+    pub status: Option<ArticleStatus>,
+    /// Reschedules the article. Implies `scheduled`.
+    pub publish_at: Option<DateTime<Utc>>,
+    /// Replaces the article's tags.
+    pub tags: Option<Vec<String>>,
 }
 
 #[derive(Debug, Serialize, Deserialize, FromRow)]
@@ -87,7 +321,333 @@ pub struct ReturnArticle {
     pub author: String,
     pub user_id: String,
     pub title: String,
+    pub slug: String,
+    pub content_markdown: String,
+    pub content_html: String,
+    pub toc: Json<Vec<TocEntry>>,
+    pub excerpt: String,
+    pub word_count: i32,
+    /// Minutes.
+    pub reading_time: i32,
+    pub status: ArticleStatus,
+    pub creation_date: NaiveDateTime,
+    pub publish_at: Option<DateTime<Utc>>,
+    /// When the article last became published.
+    pub published_at: Option<NaiveDateTime>,
+    pub tags: Json<Vec<Tag>>,
+    pub comment_count: i64,
+    /// Number of reactions of each kind.
+    pub reactions: Json<BTreeMap<String, i64>>,
+    /// Kinds the authenticated user reacted with.
+    pub my_reactions: Json<Vec<ReactionKind>>,
+    /// Whether the authenticated user bookmarked the article.
+    pub bookmarked: bool,
+}
+
+/// What listings return for an article unless `?include=content` is given.
+#[derive(Debug, Serialize)]
+pub struct ArticleSummary {
+    pub id: String,
+    pub author: String,
+    pub user_id: String,
+    pub title: String,
+    pub slug: String,
+    pub excerpt: String,
+    pub word_count: i32,
+    pub reading_time: i32,
+    pub status: ArticleStatus,
+    pub creation_date: NaiveDateTime,
+    pub publish_at: Option<DateTime<Utc>>,
+    pub published_at: Option<NaiveDateTime>,
+    pub tags: Json<Vec<Tag>>,
+    pub comment_count: i64,
+    pub reactions: Json<BTreeMap<String, i64>>,
+    pub my_reactions: Json<Vec<ReactionKind>>,
+    pub bookmarked: bool,
+}
+
+impl From<ReturnArticle> for ArticleSummary {
+    fn from(article: ReturnArticle) -> Self {
+        ArticleSummary {
+            id: article.id,
+            author: article.author,
+            user_id: article.user_id,
+            title: article.title,
+            slug: article.slug,
+            excerpt: article.excerpt,
+            word_count: article.word_count,
+            reading_time: article.reading_time,
+            status: article.status,
+            creation_date: article.creation_date,
+            publish_at: article.publish_at,
+            published_at: article.published_at,
+            tags: article.tags,
+            comment_count: article.comment_count,
+            reactions: article.reactions,
+            my_reactions: article.my_reactions,
+            bookmarked: article.bookmarked,
+        }
+    }
+}
+
+/// `?include=` on article listings: a comma separated list of extra fields.
+#[derive(Debug, Default, Deserialize)]
+pub struct ListingInclude {
+    pub include: Option<String>,
+}
+
+impl ListingInclude {
+    /// Whether the full content was asked for.
+    pub fn content(&self) -> Result<bool, AppError> {
+        let mut content = false;
+
+        for field in self.include.iter().flat_map(|include| include.split(',')) {
+            match field.trim() {
+                "content" => content = true,
+                "" => {},
+                other => return Err(AppError::Validation(format!("Unknown include: {}", other))),
+            }
+        }
+
+        Ok(content)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tag {
+    pub slug: String,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct TagCount {
+    pub slug: String,
+    pub name: String,
+    pub article_count: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagQuery {
+    pub q: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+/// An article matching a search, with its rank and a highlighted excerpt.
+/// `snippet` is escaped HTML whose only tags are `<mark>`.
+#[derive(Debug, Serialize, FromRow)]
+pub struct SearchResult {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub article: ReturnArticle,
+    pub rank: f32,
+    pub snippet: String,
+}
+
+/// `SearchResult` without the full content.
+#[derive(Debug, Serialize)]
+pub struct SearchSummary {
+    #[serde(flatten)]
+    pub article: ArticleSummary,
+    pub rank: f32,
+    pub snippet: String,
+}
+
+impl From<SearchResult> for SearchSummary {
+    fn from(result: SearchResult) -> Self {
+        SearchSummary {
+            article: result.article.into(),
+            rank: result.rank,
+            snippet: result.snippet,
+        }
+    }
+}
+
+/// Snapshot of an article taken after each change. `editor_id` is empty
+/// when the scheduled publisher made the change, and cleared when the
+/// editor's account is deleted.
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct Revision {
+    pub id: String,
+    pub article_id: String,
+    pub editor_id: Option<String>,
+    pub editor: Option<String>,
+    pub title: String,
     pub content: String,
-    pub status: String,
-    pub creation_date: NaiveDateTime
+    pub content_html: String,
+    pub status: ArticleStatus,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RevisionDiff {
+    pub from: String,
+    pub to: String,
+}
+
+/// A comment as shown to readers. Deleted comments keep their place in the
+/// thread but lose their content and author.
+#[derive(Debug, Serialize, FromRow)]
+pub struct Comment {
+    pub id: String,
+    pub article_id: String,
+    pub parent_id: Option<String>,
+    pub user_id: Option<String>,
+    pub author: Option<String>,
+    pub content: Option<String>,
+    pub deleted: bool,
+    pub depth: i32,
+    pub reply_count: i64,
+    pub creation_date: NaiveDateTime,
+    pub update_date: Option<NaiveDateTime>,
+}
+
+/// A comment with the replies loaded for it so far.
+#[derive(Debug, Serialize)]
+pub struct CommentThread {
+    #[serde(flatten)]
+    pub comment: Comment,
+    pub replies: Vec<CommentThread>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InsertComment {
+    pub content: String,
+    /// Comment being replied to.
+    pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateComment {
+    pub content: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct CommentQuery {
+    /// List the replies to this comment instead of the top-level comments.
+    pub parent: Option<String>,
+    /// Levels of replies to nest under each listed comment.
+    pub depth: Option<i32>,
+}
+
+/// Optional filters for article listings, read from the query string.
+/// `author_id` is set from the path by `/articles/{user_id}/{type}`.
+#[derive(Debug, Default, Deserialize)]
+pub struct ArticleFilters {
+    pub author: Option<String>,
+    pub status: Option<ArticleStatus>,
+    /// Tag slug.
+    pub tag: Option<String>,
+    #[serde(skip)]
+    pub author_id: Option<String>,
+    /// Only articles this user bookmarked.
+    #[serde(skip)]
+    pub bookmarked_by: Option<String>,
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ReadingList {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    pub is_public: bool,
+    pub position: i32,
+    pub article_count: i64,
+    pub creation_date: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InsertReadingList {
+    pub name: String,
+    #[serde(default)]
+    pub is_public: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateReadingList {
+    pub name: Option<String>,
+    pub is_public: Option<bool>,
+}
+
+/// A new order for reading lists or for the articles in one, as the full
+/// list of ids.
+#[derive(Debug, Deserialize)]
+pub struct Reorder {
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UserFilters {
+    pub status: Option<String>,
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateRole {
+    pub role: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ArticleStatus::*;
+
+    const ALL: [ArticleStatus; 6] = [Draft, InReview, Scheduled, Published, Unlisted, Archived];
+
+    #[test]
+    fn author_transitions() {
+        // Rows are the current status, columns the next one, in the order of `ALL`.
+        let allowed = [
+            [true, true, true, true, true, true],
+            [true, true, true, true, true, true],
+            [true, false, true, true, false, true],
+            [false, false, false, true, true, true],
+            [false, false, false, true, true, true],
+            [false, false, false, true, true, true],
+        ];
+
+        for (from, row) in ALL.iter().zip(allowed) {
+            for (to, expected) in ALL.iter().zip(row) {
+                assert_eq!(from.can_transition_to(*to), expected, "{} -> {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn editor_transitions() {
+        let allowed = [
+            [true, true, true, true, true, true],
+            [true, true, true, true, true, true],
+            [true, true, true, true, false, true],
+            [true, true, false, true, true, true],
+            [true, true, false, true, true, true],
+            [true, true, false, true, true, true],
+        ];
+
+        for (from, row) in ALL.iter().zip(allowed) {
+            for (to, expected) in ALL.iter().zip(row) {
+                assert_eq!(from.editor_can_transition_to(*to), expected, "{} -> {}", from, to);
+            }
+        }
+    }
+
+    #[test]
+    fn published_articles_cannot_go_back_to_draft() {
+        for from in [Published, Unlisted, Archived] {
+            assert!(!from.can_transition_to(Draft), "{} -> draft", from);
+            assert!(!from.can_transition_to(InReview), "{} -> in_review", from);
+        }
+    }
+
+    #[test]
+    fn status_names_round_trip() {
+        for status in ALL {
+            assert_eq!(status.as_str().parse::<ArticleStatus>().unwrap(), status);
+        }
+        assert!("deleted".parse::<ArticleStatus>().is_err());
+    }
 }
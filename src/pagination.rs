@@ -0,0 +1,345 @@
+use chrono::{ DateTime, NaiveDateTime };
+use serde::{ Serialize, Deserialize };
+use sqlx::{ Postgres, QueryBuilder };
+
+use crate::errors::{ AppError, AppResult };
+
+pub const MAX_LIMIT: i64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// Query string accepted by every paginated listing.
+#[derive(Debug, Default, Deserialize)]
+pub struct PageParams {
+    pub limit: Option<i64>,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub order: Option<SortOrder>,
+}
+
+/// Position of a row in a listing ordered by `(timestamp, id)`. Clients only
+/// ever see it as an opaque string.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub timestamp: NaiveDateTime,
+    pub id: String,
+}
+
+#[derive(Debug)]
+pub struct Page {
+    pub limit: i64,
+    pub order: SortOrder,
+    pub after: Option<Cursor>,
+    pub before: Option<Cursor>,
+}
+
+/// Page of a listing ordered by a computed score, such as search rank,
+/// where a `(timestamp, id)` keyset does not apply. Cursors are offsets.
+#[derive(Debug)]
+pub struct OffsetPage {
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<String>,
+    pub prev_cursor: Option<String>,
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        hex::encode(format!("{}|{}", self.timestamp.and_utc().timestamp_micros(), self.id))
+    }
+
+    pub fn decode(value: &str) -> AppResult<Cursor> {
+        let invalid = || AppError::Validation("Invalid cursor".to_string());
+
+        let raw = hex::decode(value).map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (micros, id) = raw.split_once('|').ok_or_else(invalid)?;
+        let micros = micros.parse::<i64>().map_err(|_| invalid())?;
+        let timestamp = DateTime::from_timestamp(
+            micros.div_euclid(1_000_000),
+            (micros.rem_euclid(1_000_000) * 1_000) as u32)
+            .ok_or_else(invalid)?
+            .naive_utc();
+
+        Ok(Cursor { timestamp, id: id.to_string() })
+    }
+}
+
+impl PageParams {
+    pub fn into_page(self, default_limit: i64, default_order: SortOrder) -> AppResult<Page> {
+        let limit = self.limit.unwrap_or(default_limit);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(AppError::Validation(format!("limit must be between 1 and {}", MAX_LIMIT)));
+        }
+
+        if self.before.is_some() && self.after.is_some() {
+            return Err(AppError::Validation("Use either before or after, not both".to_string()));
+        }
+
+        Ok(Page {
+            limit,
+            order: self.order.unwrap_or(default_order),
+            after: self.after.as_deref().map(Cursor::decode).transpose()?,
+            before: self.before.as_deref().map(Cursor::decode).transpose()?,
+        })
+    }
+
+    /// Like `into_page`, for listings paged by offset. `order` is ignored.
+    pub fn into_offset_page(self, default_limit: i64) -> AppResult<OffsetPage> {
+        let limit = self.limit.unwrap_or(default_limit);
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err(AppError::Validation(format!("limit must be between 1 and {}", MAX_LIMIT)));
+        }
+
+        let offset = match (self.after.as_deref(), self.before.as_deref()) {
+            (Some(_), Some(_)) => return Err(AppError::Validation("Use either before or after, not both".to_string())),
+            (Some(after), None) => decode_offset(after)?,
+            (None, Some(before)) => (decode_offset(before)? - limit).max(0),
+            (None, None) => 0,
+        };
+
+        Ok(OffsetPage { limit, offset })
+    }
+}
+
+impl OffsetPage {
+    /// Appends `LIMIT` and `OFFSET`, asking for one extra row so `finish`
+    /// can tell whether another page exists.
+    pub fn push_limit(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        builder.push(" LIMIT ");
+        builder.push_bind(self.limit + 1);
+        builder.push(" OFFSET ");
+        builder.push_bind(self.offset);
+    }
+
+    pub fn finish<T>(&self, mut rows: Vec<T>) -> Paginated<T> {
+        let has_more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+
+        let next_cursor = if has_more {
+            Some(encode_offset(self.offset + rows.len() as i64))
+        } else {
+            None
+        };
+        let prev_cursor = if self.offset > 0 {
+            Some(encode_offset(self.offset))
+        } else {
+            None
+        };
+
+        Paginated { items: rows, next_cursor, prev_cursor }
+    }
+}
+
+fn encode_offset(offset: i64) -> String {
+    hex::encode(offset.to_string())
+}
+
+fn decode_offset(value: &str) -> AppResult<i64> {
+    hex::decode(value)
+        .ok()
+        .and_then(|raw| String::from_utf8(raw).ok())
+        .and_then(|raw| raw.parse::<i64>().ok())
+        .filter(|offset| *offset >= 0)
+        .ok_or_else(|| AppError::Validation("Invalid cursor".to_string()))
+}
+
+impl Page {
+    fn is_backward(&self) -> bool {
+        self.before.is_some()
+    }
+
+    /// Appends the keyset condition, `ORDER BY` and `LIMIT` for this page.
+    /// The builder must already end inside a `WHERE` clause. One extra row
+    /// is requested so `finish` can tell whether another page exists.
+    pub fn push_keyset(&self, builder: &mut QueryBuilder<'_, Postgres>, timestamp_column: &str, id_column: &str) {
+        // Walking backwards from a `before` cursor reverses the scan; `finish`
+        // flips the rows back into the requested order.
+        let ascending = (self.order == SortOrder::Asc) != self.is_backward();
+
+        if let Some(cursor) = self.after.as_ref().or(self.before.as_ref()) {
+            builder.push(format!(
+                " AND ({}, {}) {} (",
+                timestamp_column, id_column, if ascending { ">" } else { "<" }));
+            builder.push_bind(cursor.timestamp);
+            builder.push(", ");
+            builder.push_bind(cursor.id.clone());
+            builder.push(")");
+        }
+
+        self.push_order_by(builder, timestamp_column, id_column);
+        builder.push(" LIMIT ");
+        builder.push_bind(self.limit + 1);
+    }
+
+    /// Appends just the `ORDER BY` of `push_keyset`, for an outer query that
+    /// has to keep the order of a page selected in a subquery.
+    pub fn push_order_by(&self, builder: &mut QueryBuilder<'_, Postgres>, timestamp_column: &str, id_column: &str) {
+        let ascending = (self.order == SortOrder::Asc) != self.is_backward();
+        let direction = if ascending { "ASC" } else { "DESC" };
+
+        builder.push(format!(
+            " ORDER BY {} {}, {} {}",
+            timestamp_column, direction, id_column, direction));
+    }
+
+    /// Trims the extra row fetched by `push_keyset` and works out the
+    /// cursors for the neighbouring pages.
+    pub fn finish<T>(&self, mut rows: Vec<T>, cursor_of: impl Fn(&T) -> Cursor) -> Paginated<T> {
+        let has_more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit as usize);
+
+        if self.is_backward() {
+            rows.reverse();
+        }
+
+        let first = rows.first().map(|row| cursor_of(row).encode());
+        let last = rows.last().map(|row| cursor_of(row).encode());
+
+        let (next_cursor, prev_cursor) = if self.is_backward() {
+            (last, if has_more { first } else { None })
+        } else {
+            (if has_more { last } else { None }, if self.after.is_some() { first } else { None })
+        };
+
+        Paginated { items: rows, next_cursor, prev_cursor }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{ Duration, NaiveDate };
+
+    fn cursor(micros: i64, id: &str) -> Cursor {
+        Cursor {
+            timestamp: DateTime::from_timestamp(0, 0).unwrap().naive_utc() + Duration::microseconds(micros),
+            id: id.to_string(),
+        }
+    }
+
+    fn page(order: SortOrder, after: Option<Cursor>, before: Option<Cursor>) -> Page {
+        Page { limit: 2, order, after, before }
+    }
+
+    fn keyset_sql(page: &Page) -> String {
+        let mut builder = QueryBuilder::new("SELECT * FROM articles WHERE true");
+        page.push_keyset(&mut builder, "creation_date", "id");
+        builder.sql().to_string()
+    }
+
+    #[test]
+    fn cursor_round_trips_with_microseconds() {
+        let timestamp = NaiveDate::from_ymd_opt(2024, 4, 1).unwrap()
+            .and_hms_micro_opt(12, 30, 15, 123_456).unwrap();
+        let original = Cursor { timestamp, id: "7d3c6a0e-1f2b-4c5d-8e9f-0a1b2c3d4e5f".to_string() };
+
+        assert_eq!(Cursor::decode(&original.encode()).unwrap(), original);
+    }
+
+    #[test]
+    fn cursor_round_trips_before_the_epoch() {
+        let original = cursor(-1_500_001, "a");
+
+        assert_eq!(Cursor::decode(&original.encode()).unwrap(), original);
+    }
+
+    #[test]
+    fn cursor_id_may_contain_the_separator() {
+        let original = cursor(0, "a|b");
+
+        assert_eq!(Cursor::decode(&original.encode()).unwrap(), original);
+    }
+
+    #[test]
+    fn malformed_cursors_are_rejected() {
+        for value in ["zz", &hex::encode("no separator"), &hex::encode("abc|id"), &hex::encode([0xff, b'|'])] {
+            assert!(matches!(Cursor::decode(value), Err(AppError::Validation(_))), "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn first_page_has_no_keyset_condition() {
+        assert_eq!(
+            keyset_sql(&page(SortOrder::Desc, None, None)),
+            "SELECT * FROM articles WHERE true ORDER BY creation_date DESC, id DESC LIMIT $1");
+    }
+
+    #[test]
+    fn after_cursor_continues_in_the_requested_order() {
+        assert_eq!(
+            keyset_sql(&page(SortOrder::Desc, Some(cursor(0, "a")), None)),
+            "SELECT * FROM articles WHERE true AND (creation_date, id) < ($1, $2) \
+            ORDER BY creation_date DESC, id DESC LIMIT $3");
+        assert_eq!(
+            keyset_sql(&page(SortOrder::Asc, Some(cursor(0, "a")), None)),
+            "SELECT * FROM articles WHERE true AND (creation_date, id) > ($1, $2) \
+            ORDER BY creation_date ASC, id ASC LIMIT $3");
+    }
+
+    #[test]
+    fn before_cursor_scans_in_reverse() {
+        assert_eq!(
+            keyset_sql(&page(SortOrder::Desc, None, Some(cursor(0, "a")))),
+            "SELECT * FROM articles WHERE true AND (creation_date, id) > ($1, $2) \
+            ORDER BY creation_date ASC, id ASC LIMIT $3");
+    }
+
+    #[test]
+    fn finish_trims_the_extra_row_and_sets_cursors() {
+        let rows = vec![cursor(3, "c"), cursor(2, "b"), cursor(1, "a")];
+        let result = page(SortOrder::Desc, Some(cursor(4, "d")), None).finish(rows, Cursor::clone);
+
+        assert_eq!(result.items, vec![cursor(3, "c"), cursor(2, "b")]);
+        assert_eq!(result.next_cursor, Some(cursor(2, "b").encode()));
+        assert_eq!(result.prev_cursor, Some(cursor(3, "c").encode()));
+    }
+
+    #[test]
+    fn finish_restores_the_order_of_a_backward_page() {
+        // Rows arrive oldest first from the reversed scan of a DESC listing.
+        let rows = vec![cursor(2, "b"), cursor(3, "c")];
+        let result = page(SortOrder::Desc, None, Some(cursor(1, "a"))).finish(rows, Cursor::clone);
+
+        assert_eq!(result.items, vec![cursor(3, "c"), cursor(2, "b")]);
+        assert_eq!(result.next_cursor, Some(cursor(2, "b").encode()));
+        assert_eq!(result.prev_cursor, None);
+    }
+
+    #[test]
+    fn page_params_are_validated() {
+        let params = |limit, before: Option<&str>, after: Option<&str>| PageParams {
+            limit,
+            before: before.map(str::to_string),
+            after: after.map(str::to_string),
+            order: None,
+        };
+        let valid = cursor(0, "a").encode();
+
+        assert!(params(Some(0), None, None).into_page(20, SortOrder::Desc).is_err());
+        assert!(params(Some(MAX_LIMIT + 1), None, None).into_page(20, SortOrder::Desc).is_err());
+        assert!(params(None, Some(&valid), Some(&valid)).into_page(20, SortOrder::Desc).is_err());
+        assert_eq!(params(None, None, None).into_page(20, SortOrder::Desc).unwrap().limit, 20);
+    }
+
+    #[test]
+    fn offset_cursors_step_back_by_a_page() {
+        let params = PageParams { limit: Some(10), before: Some(encode_offset(15)), ..PageParams::default() };
+        assert_eq!(params.into_offset_page(20).unwrap().offset, 5);
+
+        let params = PageParams { limit: Some(10), before: Some(encode_offset(5)), ..PageParams::default() };
+        assert_eq!(params.into_offset_page(20).unwrap().offset, 0);
+
+        assert!(decode_offset(&encode_offset(-1)).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use std::str::FromStr;
+
+use serde::{ Serialize, Deserialize };
+use sqlx::PgPool;
+
+use crate::auth::AuthUser;
+use crate::db::user_table_helper;
+use crate::errors::{ AppError, AppResult };
+
+/// Stored in `users.role`. New accounts start as authors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Reader,
+    Author,
+    Editor,
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Create articles and manage one's own.
+    WriteArticles,
+    /// Change articles written by someone else.
+    EditAnyArticle,
+    /// See full accounts, suspend, reactivate, delete and change roles, and
+    /// force articles out of `published`.
+    ManageUsers,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Author => "author",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn can(self, permission: Permission) -> bool {
+        match permission {
+            Permission::WriteArticles => self != Role::Reader,
+            Permission::EditAnyArticle => matches!(self, Role::Editor | Role::Admin),
+            Permission::ManageUsers => self == Role::Admin,
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "reader" => Ok(Role::Reader),
+            "author" => Ok(Role::Author),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            _ => Err(AppError::Validation(format!("Unknown role: {}", value))),
+        }
+    }
+}
+
+/// Looks up the current role of an account that may act, failing for
+/// suspended and unverified ones. `AuthUser` calls it on every request so
+/// role changes and suspensions apply before tokens expire.
+pub async fn active_role(pool: &PgPool, user_id: &str) -> AppResult<Role> {
+    let (role, account_status) = user_table_helper::get_role_and_status(pool, user_id).await?;
+
+    if account_status == "suspended" {
+        return Err(AppError::Forbidden("Account is suspended".to_string()));
+    }
+    if account_status == "pending_verification" {
+        return Err(AppError::Forbidden("Email address is not verified".to_string()));
+    }
+
+    role.parse()
+}
+
+/// Fails with 403 unless the caller's role grants `permission`.
+pub fn authorize(user: &AuthUser, permission: Permission) -> AppResult<Role> {
+    if !user.role.can(permission) {
+        return Err(AppError::Forbidden("You do not have permission to do this".to_string()));
+    }
+
+    Ok(user.role)
+}
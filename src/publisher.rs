@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use actix_web::rt;
+use sqlx::PgPool;
+
+use crate::db::article_table_helper;
+
+/// Most articles published in one statement.
+const BATCH_SIZE: i64 = 100;
+
+/// Starts the background task that publishes scheduled articles. Every
+/// server instance runs its own; `publish_due_articles` keeps them from
+/// publishing the same article twice.
+pub fn spawn(pool: PgPool, every: Duration) {
+    rt::spawn(async move {
+        let mut interval = rt::time::interval(every);
+
+        loop {
+            interval.tick().await;
+            publish_due(&pool).await;
+        }
+    });
+}
+
+async fn publish_due(pool: &PgPool) {
+    loop {
+        match article_table_helper::publish_due_articles(pool, BATCH_SIZE).await {
+            Ok(published) => {
+                for id in &published {
+                    log::info!("Published scheduled article {}", id);
+                }
+
+                if (published.len() as i64) < BATCH_SIZE {
+                    break;
+                }
+            },
+            Err(e) => {
+                log::error!("Failed to publish scheduled articles: {}", e);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use actix_web::{ delete, get, put, web, HttpResponse };
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{ auth::AuthUser, db, models };
+use crate::errors::{ AppError, AppResult };
+use crate::pagination::{ PageParams, SortOrder };
+use crate::permissions::{ self, Permission, Role };
+use db::{ article_table_helper, token_table_helper, user_table_helper };
+use models::{ ArticleStatus, UpdateArticle, UpdateRole, UserFilters };
+
+pub fn admin_scopes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .service(list_users_handler)
+            .service(suspend_user_handler)
+            .service(reactivate_user_handler)
+            .service(update_role_handler)
+            .service(delete_user_handler)
+            .service(unpublish_article_handler)
+    );
+}
+
+#[get("/users")]
+async fn list_users_handler(pool: web::Data<PgPool>, user: AuthUser, page: web::Query<PageParams>, filters: web::Query<UserFilters>) -> AppResult<HttpResponse> {
+    permissions::authorize(&user, Permission::ManageUsers)?;
+
+    let page = page.into_inner().into_page(20, SortOrder::Asc)?;
+    let users = user_table_helper::list_users(&pool, &filters, &page).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "length": users.items.len(),
+        "users": users.items,
+        "next_cursor": users.next_cursor,
+        "prev_cursor": users.prev_cursor
+    })))
+}
+
+#[put("/users/{id}/suspend")]
+async fn suspend_user_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> AppResult<HttpResponse> {
+    permissions::authorize(&user, Permission::ManageUsers)?;
+    let target = managed_user(&pool, &user, path.into_inner()).await?;
+
+    user_table_helper::update_account_status(&pool, &target.email, "suspended").await?;
+    token_table_helper::revoke_user_tokens(&pool, &target.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "User suspended successfully"
+    })))
+}
+
+#[put("/users/{id}/reactivate")]
+async fn reactivate_user_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> AppResult<HttpResponse> {
+    permissions::authorize(&user, Permission::ManageUsers)?;
+    let target = managed_user(&pool, &user, path.into_inner()).await?;
+    if target.account_status != "suspended" {
+        return Err(AppError::Conflict("Only suspended accounts can be reactivated".to_string()));
+    }
+
+    user_table_helper::update_account_status(&pool, &target.email, "active").await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "User reactivated successfully"
+    })))
+}
+
+#[put("/users/{id}/role")]
+async fn update_role_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<String>, data: web::Json<UpdateRole>) -> AppResult<HttpResponse> {
+    permissions::authorize(&user, Permission::ManageUsers)?;
+    let target = managed_user(&pool, &user, path.into_inner()).await?;
+    let role: Role = data.role.parse()?;
+
+    user_table_helper::update_role(&pool, &target.id, role.as_str()).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "User role updated successfully"
+    })))
+}
+
+#[delete("/users/{id}")]
+async fn delete_user_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> AppResult<HttpResponse> {
+    permissions::authorize(&user, Permission::ManageUsers)?;
+    let target = managed_user(&pool, &user, path.into_inner()).await?;
+
+    user_table_helper::delete_user(&pool, &target.email).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "User deleted successfully"
+    })))
+}
+
+/// Takes a published article down by archiving it. Its author can publish
+/// it again.
+#[put("/articles/{id}/unpublish")]
+async fn unpublish_article_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> AppResult<HttpResponse> {
+    let role = permissions::authorize(&user, Permission::ManageUsers)?;
+
+    let article = article_table_helper::get_article_by_id(&pool, path.into_inner()).await?;
+    if article.status != ArticleStatus::Published {
+        return Err(AppError::Conflict("Article is not published".to_string()));
+    }
+
+    article_table_helper::update_article(&pool, &user.id, role, UpdateArticle {
+        id: article.id,
+        title: None,
+        content: None,
+        status: Some(ArticleStatus::Archived),
+        publish_at: None,
+        tags: None,
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Article unpublished successfully"
+    })))
+}
+
+/// Loads the account an administrator is acting on. Administrators cannot
+/// suspend, demote or delete themselves, so the last admin cannot lock
+/// everyone out.
+async fn managed_user(pool: &PgPool, admin: &AuthUser, user_id: String) -> AppResult<models::User> {
+    if admin.id == user_id {
+        return Err(AppError::Forbidden("Administrators cannot change their own account here".to_string()));
+    }
+
+    user_table_helper::get_user_by_id(pool, user_id).await
+}
@@ -1,120 +1,270 @@
-use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use actix_web::http::header;
 use serde_json::json;
-use serde::{ Deserialize, Serialize };
+use sqlx::PgPool;
 
-use crate::{ db, models::{self, UpdateArticle} };
-use db::article_table_helper;
-use models::InsertArticle;
+use crate::{ auth::AuthUser, db, diff, models::{self, UpdateArticle}, search };
+use crate::errors::{ AppError, AppResult };
+use crate::pagination::{ PageParams, Paginated, SortOrder };
+use crate::permissions::{ self, Permission };
+use crate::routes::{ comment_routes, reaction_routes, reading_list_routes };
+use db::{ article_table_helper, revision_table_helper };
+use models::{
+    Article, ArticleFilters, ArticleStatus, ArticleSummary, InsertArticle, ListingInclude,
+    ReturnArticle, RevisionDiff, SearchQuery, SearchSummary
+};
 
 pub fn article_scopes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/articles")
             .service(index)
             .service(latest_articles_handler)
+            .service(search_handler)
             .service(create_article)
+            .service(list_revisions_handler)
+            .service(revision_diff_handler)
+            .service(get_revision_handler)
+            .service(restore_revision_handler)
+            .service(comment_routes::list_comments_handler)
+            .service(comment_routes::create_comment_handler)
+            .service(reaction_routes::list_reactions_handler)
+            .service(reaction_routes::add_reaction_handler)
+            .service(reaction_routes::remove_reaction_handler)
+            .service(reading_list_routes::add_bookmark_handler)
+            .service(reading_list_routes::remove_bookmark_handler)
             .service(user_articles_handler)
             .service(update_article_status_handler)
             .service(delete_article_handler)
+            .service(get_article_handler)
     );
+    cfg.service(permalink_handler);
+    cfg.service(feed_handler);
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Info { user_id: String }
-
 #[post("/new")]
-async fn create_article(article: web::Json<InsertArticle>) -> impl Responder {
+async fn create_article(pool: web::Data<PgPool>, user: AuthUser, article: web::Json<InsertArticle>) -> AppResult<HttpResponse> {
+    permissions::authorize(&user, Permission::WriteArticles)?;
+
     let article = article.into_inner();
-    let result = article_table_helper::insert_article(article).await;
-
-    match result {
-        Ok(_) => {
-            HttpResponse::Ok()
-                .json(json!({
-                    "status": "ok",
-                    "message": "Article created successfully"
-                }))
-        },
-        Err(e) => {
-            println!("{:?}", &e);
-            HttpResponse::InternalServerError()
-                .json(json!({
-                    "status": "failed",
-                    "message": e.to_string()
-                }))
-        }
-    }
+    let article_id = article_table_helper::insert_article(&pool, user.id, article).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Article created successfully",
+        "id": article_id
+    })))
 }
 
 #[get("/all")]
-async fn index() -> impl Responder {
-    let articles = article_table_helper::get_all_articles().await;
-    HttpResponse::Ok().json(json!({
-        "status": "ok",
-        "articles": articles
-    }))
+async fn index(pool: web::Data<PgPool>, user: Option<AuthUser>, page: web::Query<PageParams>, filters: web::Query<ArticleFilters>, include: web::Query<ListingInclude>) -> AppResult<HttpResponse> {
+    let page = page.into_inner().into_page(20, SortOrder::Asc)?;
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+    let articles = article_table_helper::list_articles(&pool, &filters, viewer_id, &page).await?;
+
+    Ok(articles_response(articles, include.content()?))
 }
 
 #[get("/latest")]
-async fn latest_articles_handler() -> impl Responder {
-    let articles = article_table_helper::get_latest_articles().await;
-    HttpResponse::Ok().json(json!({
+async fn latest_articles_handler(pool: web::Data<PgPool>, page: web::Query<PageParams>, filters: web::Query<ArticleFilters>, include: web::Query<ListingInclude>) -> AppResult<HttpResponse> {
+    let page = page.into_inner().into_page(10, SortOrder::Desc)?;
+    let filters = ArticleFilters {
+        status: Some(ArticleStatus::Published),
+        ..filters.into_inner()
+    };
+    let articles = article_table_helper::list_articles(&pool, &filters, None, &page).await?;
+
+    Ok(articles_response(articles, include.content()?))
+}
+
+/// Published articles by the authors the caller follows, most recently
+/// published first.
+#[get("/feed")]
+async fn feed_handler(pool: web::Data<PgPool>, user: AuthUser, page: web::Query<PageParams>, include: web::Query<ListingInclude>) -> AppResult<HttpResponse> {
+    let page = page.into_inner().into_page(20, SortOrder::Desc)?;
+    let articles = article_table_helper::list_feed_articles(&pool, &user.id, &page).await?;
+
+    Ok(articles_response(articles, include.content()?))
+}
+
+#[get("/search")]
+async fn search_handler(pool: web::Data<PgPool>, user: Option<AuthUser>, query: web::Query<SearchQuery>, page: web::Query<PageParams>, include: web::Query<ListingInclude>) -> AppResult<HttpResponse> {
+    let tsquery = search::to_tsquery(&query.q)?;
+    let page = page.into_inner().into_offset_page(20)?;
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+    let results = article_table_helper::search_articles(&pool, &tsquery, viewer_id, &page).await?;
+    let items = if include.content()? {
+        json!(results.items)
+    } else {
+        json!(results.items.into_iter().map(SearchSummary::from).collect::<Vec<_>>())
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
         "status": "ok",
-        "articles": articles
-    }))
+        "articles": items,
+        "next_cursor": results.next_cursor,
+        "prev_cursor": results.prev_cursor
+    })))
 }
 
-#[get("/{user_id}/{type}")]
-async fn user_articles_handler(path: web::Path<(String, String)>) -> impl Responder {
-    let articles = article_table_helper::get_articles_by_user_id(path.0.clone(), path.1.clone()).await;
-    HttpResponse::Ok().json(json!({
+#[get("/{id}")]
+async fn get_article_handler(pool: web::Data<PgPool>, user: Option<AuthUser>, id: web::Path<String>) -> AppResult<HttpResponse> {
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+    let article = article_table_helper::get_article(&pool, id.into_inner(), viewer_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
         "status": "ok",
-        "articles": articles
-    }))
+        "article": article
+    })))
 }
 
-#[put("/update")]
-async fn update_article_status_handler(data: web::Json<UpdateArticle>) -> impl Responder {
-    let result = article_table_helper::update_article(data.into_inner()).await;
-
-    match result {
-        Ok(_) => {
-            HttpResponse::Ok()
-                .json(json!({
-                    "status": "ok",
-                    "message": "Article status updated successfully"
-                }))
-        },
-        Err(e) => {
-            println!("{:?}", &e);
-            HttpResponse::InternalServerError()
-                .json(json!({
-                    "status": "failed",
-                    "message": e.to_string()
-                }))
-        }
+/// Serves an article at `/@{username}/{slug}`. Slugs an article had before
+/// its title changed redirect permanently to the current one.
+#[get("/@{username}/{slug}")]
+async fn permalink_handler(pool: web::Data<PgPool>, user: Option<AuthUser>, path: web::Path<(String, String)>) -> AppResult<HttpResponse> {
+    let (username, slug) = path.into_inner();
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+
+    if let Some(current) = article_table_helper::renamed_slug(&pool, &username, &slug, viewer_id).await? {
+        return Ok(HttpResponse::MovedPermanently()
+            .insert_header((header::LOCATION, format!("/@{}/{}", username, current)))
+            .finish());
     }
+
+    let article = article_table_helper::get_article_by_slug(&pool, &username, &slug, viewer_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "article": article
+    })))
+}
+
+#[get("/{user_id}/{type}")]
+async fn user_articles_handler(pool: web::Data<PgPool>, user: Option<AuthUser>, path: web::Path<(String, String)>, page: web::Query<PageParams>, include: web::Query<ListingInclude>) -> AppResult<HttpResponse> {
+    let (user_id, type_) = path.into_inner();
+    let status = match type_.as_str() {
+        "all" => None,
+        status => Some(status.parse::<ArticleStatus>()?),
+    };
+    let page = page.into_inner().into_page(20, SortOrder::Desc)?;
+    let filters = ArticleFilters {
+        author_id: Some(user_id),
+        status,
+        ..Default::default()
+    };
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+    let articles = article_table_helper::list_articles(&pool, &filters, viewer_id, &page).await?;
+
+    Ok(articles_response(articles, include.content()?))
+}
+
+#[put("/update")]
+async fn update_article_status_handler(pool: web::Data<PgPool>, user: AuthUser, data: web::Json<UpdateArticle>) -> AppResult<HttpResponse> {
+    let role = permissions::authorize(&user, Permission::WriteArticles)?;
+    article_table_helper::update_article(&pool, &user.id, role, data.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Article updated successfully"
+    })))
 }
 
 #[delete("/delete/{id}")]
-async fn delete_article_handler(id: web::Path<String>, data: web::Query<Info>) -> impl Responder {
-    let data = data.into_inner();
-    let result = article_table_helper::delete_article(id.into_inner(), data.user_id).await;
-
-    match result {
-        Ok(_) => {
-            HttpResponse::Ok()
-                .json(json!({
-                    "status": "ok",
-                    "message": "Article deleted successfully"
-                }))
-        },
-        Err(e) => {
-            println!("{:?}", &e);
-            HttpResponse::InternalServerError()
-                .json(json!({
-                    "status": "failed",
-                    "message": e.to_string()
-                }))
-        }
+async fn delete_article_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>) -> AppResult<HttpResponse> {
+    article_table_helper::delete_article(&pool, id.into_inner(), user.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Article deleted successfully"
+    })))
+}
+
+#[get("/{id}/revisions")]
+async fn list_revisions_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>, page: web::Query<PageParams>) -> AppResult<HttpResponse> {
+    let article = history_of(&pool, &user, id.into_inner()).await?;
+    let page = page.into_inner().into_page(20, SortOrder::Desc)?;
+    let revisions = revision_table_helper::list_revisions(&pool, &article.id, &page).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "revisions": revisions.items,
+        "next_cursor": revisions.next_cursor,
+        "prev_cursor": revisions.prev_cursor
+    })))
+}
+
+#[get("/{id}/revisions/{revision_id}")]
+async fn get_revision_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<(String, String)>) -> AppResult<HttpResponse> {
+    let (id, revision_id) = path.into_inner();
+    let article = history_of(&pool, &user, id).await?;
+    let revision = revision_table_helper::get_revision(&pool, &article.id, &revision_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "revision": revision
+    })))
+}
+
+#[get("/{id}/diff")]
+async fn revision_diff_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>, query: web::Query<RevisionDiff>) -> AppResult<HttpResponse> {
+    let article = history_of(&pool, &user, id.into_inner()).await?;
+    let from = revision_table_helper::get_revision(&pool, &article.id, &query.from).await?;
+    let to = revision_table_helper::get_revision(&pool, &article.id, &query.to).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "from": from.id,
+        "to": to.id,
+        "title": diff::line_diff(&from.title, &to.title),
+        "content": diff::line_diff(&from.content, &to.content)
+    })))
+}
+
+#[post("/{id}/revisions/{revision_id}/restore")]
+async fn restore_revision_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<(String, String)>) -> AppResult<HttpResponse> {
+    let (id, revision_id) = path.into_inner();
+    let role = permissions::authorize(&user, Permission::WriteArticles)?;
+    let revision = revision_table_helper::get_revision(&pool, &id, &revision_id).await?;
+
+    article_table_helper::update_article(&pool, &user.id, role, UpdateArticle {
+        id,
+        title: Some(revision.title),
+        content: Some(revision.content),
+        status: None,
+        publish_at: None,
+        tags: None,
+    }).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Revision restored successfully"
+    })))
+}
+
+/// Loads an article whose history the caller may read: its author, editors
+/// and admins.
+async fn history_of(pool: &PgPool, user: &AuthUser, article_id: String) -> AppResult<Article> {
+    let article = article_table_helper::get_article_by_id(pool, article_id).await?;
+
+    if article.user_id != user.id && !user.role.can(Permission::EditAnyArticle) {
+        return Err(AppError::Forbidden("Only the author or an editor can see this article's history".to_string()));
     }
+
+    Ok(article)
+}
+
+/// Envelope shared by article listings. Articles are summarized unless
+/// `include_content` is set.
+pub fn articles_response(articles: Paginated<ReturnArticle>, include_content: bool) -> HttpResponse {
+    let items = if include_content {
+        json!(articles.items)
+    } else {
+        json!(articles.items.into_iter().map(ArticleSummary::from).collect::<Vec<_>>())
+    };
+
+    HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "articles": items,
+        "next_cursor": articles.next_cursor,
+        "prev_cursor": articles.prev_cursor
+    }))
 }
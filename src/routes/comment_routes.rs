@@ -0,0 +1,86 @@
+use actix_web::{ delete, get, post, put, web, HttpResponse };
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{ auth::AuthUser, db, models };
+use crate::config::Config;
+use crate::errors::{ AppError, AppResult };
+use crate::models::ArticleStatus;
+use crate::pagination::{ PageParams, SortOrder };
+use db::{ article_table_helper, comment_table_helper };
+use models::{ CommentQuery, InsertComment, UpdateComment };
+
+/// Levels of replies returned by default when listing comments.
+const DEFAULT_REPLY_DEPTH: i32 = 2;
+
+/// `/comments/{id}` routes. The routes under `/articles/{id}/comments` are
+/// registered by `article_routes`.
+pub fn comment_scopes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/comments")
+            .service(update_comment_handler)
+            .service(delete_comment_handler)
+    );
+}
+
+#[get("/{id}/comments")]
+pub async fn list_comments_handler(pool: web::Data<PgPool>, config: web::Data<Config>, user: Option<AuthUser>, id: web::Path<String>, query: web::Query<CommentQuery>, page: web::Query<PageParams>) -> AppResult<HttpResponse> {
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+    let article = article_table_helper::get_article(&pool, id.into_inner(), viewer_id).await?;
+
+    let depth = query.depth.unwrap_or(DEFAULT_REPLY_DEPTH.min(config.comment_max_depth));
+    if !(0..=config.comment_max_depth).contains(&depth) {
+        return Err(AppError::Validation(format!("depth must be between 0 and {}", config.comment_max_depth)));
+    }
+
+    let page = page.into_inner().into_page(20, SortOrder::Asc)?;
+    let comments = comment_table_helper::list_comments(&pool, &article.id, query.parent.as_deref(), depth, &page).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "comments": comments.items,
+        "next_cursor": comments.next_cursor,
+        "prev_cursor": comments.prev_cursor
+    })))
+}
+
+#[post("/{id}/comments")]
+pub async fn create_comment_handler(pool: web::Data<PgPool>, config: web::Data<Config>, user: AuthUser, id: web::Path<String>, data: web::Json<InsertComment>) -> AppResult<HttpResponse> {
+    let article = article_table_helper::get_article_by_id(&pool, id.into_inner()).await?;
+    if article.status != ArticleStatus::Published {
+        if article.status.is_public() || article.user_id == user.id {
+            return Err(AppError::Conflict("Comments are only open on published articles".to_string()));
+        }
+        return Err(AppError::NotFound("Article not found".to_string()));
+    }
+
+    let comment_id = comment_table_helper::insert_comment(
+        &pool, &article.id, &user.id, data.into_inner(), config.comment_max_depth).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Comment created successfully",
+        "id": comment_id
+    })))
+}
+
+#[put("/{id}")]
+async fn update_comment_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>, data: web::Json<UpdateComment>) -> AppResult<HttpResponse> {
+    comment_table_helper::update_comment(&pool, &id, &user.id, &data.content).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Comment updated successfully"
+    })))
+}
+
+#[delete("/{id}")]
+async fn delete_comment_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>) -> AppResult<HttpResponse> {
+    let role = user.role;
+    comment_table_helper::delete_comment(&pool, &id, &user.id, role).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Comment deleted successfully"
+    })))
+}
@@ -1,2 +1,8 @@
 pub mod user_routes;
 pub mod article_routes;
+pub mod admin_routes;
+pub mod tag_routes;
+pub mod comment_routes;
+pub mod reaction_routes;
+pub mod reading_list_routes;
+pub mod two_factor_routes;
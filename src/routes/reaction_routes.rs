@@ -0,0 +1,62 @@
+use actix_web::{ delete, get, put, web, HttpResponse };
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{ auth::AuthUser, db, models };
+use crate::errors::{ AppError, AppResult };
+use crate::pagination::{ PageParams, SortOrder };
+use db::{ article_table_helper, reaction_table_helper };
+use models::{ ReactionFilters, ReactionKind };
+
+// The routes below live under `/articles` and are registered by
+// `article_routes`.
+
+#[get("/{id}/reactions")]
+pub async fn list_reactions_handler(pool: web::Data<PgPool>, user: Option<AuthUser>, id: web::Path<String>, filters: web::Query<ReactionFilters>, page: web::Query<PageParams>) -> AppResult<HttpResponse> {
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+    let article = article_table_helper::get_article(&pool, id.into_inner(), viewer_id).await?;
+
+    let page = page.into_inner().into_page(20, SortOrder::Asc)?;
+    let reactions = reaction_table_helper::list_reactions(&pool, &article.id, filters.kind, &page).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "reactions": reactions.items,
+        "next_cursor": reactions.next_cursor,
+        "prev_cursor": reactions.prev_cursor
+    })))
+}
+
+#[put("/{id}/reactions/{kind}")]
+pub async fn add_reaction_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<(String, String)>) -> AppResult<HttpResponse> {
+    let (id, kind) = path.into_inner();
+    let kind: ReactionKind = kind.parse()?;
+    let article = article_table_helper::get_article(&pool, id, Some(&user.id)).await?;
+    if !article.status.is_public() {
+        return Err(AppError::Conflict("Only published and unlisted articles can be reacted to".to_string()));
+    }
+    reaction_table_helper::add_reaction(&pool, &article.id, &user.id, kind).await?;
+
+    reactions_response(&pool, &article.id).await
+}
+
+#[delete("/{id}/reactions/{kind}")]
+pub async fn remove_reaction_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<(String, String)>) -> AppResult<HttpResponse> {
+    let (id, kind) = path.into_inner();
+    let kind: ReactionKind = kind.parse()?;
+    let article = article_table_helper::get_article(&pool, id, Some(&user.id)).await?;
+    reaction_table_helper::remove_reaction(&pool, &article.id, &user.id, kind).await?;
+
+    reactions_response(&pool, &article.id).await
+}
+
+/// Answers a change to the caller's reactions with the article's counts
+/// after it.
+async fn reactions_response(pool: &PgPool, article_id: &str) -> AppResult<HttpResponse> {
+    let counts = reaction_table_helper::reaction_counts(pool, article_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "reactions": counts
+    })))
+}
@@ -0,0 +1,169 @@
+use actix_web::{ delete, get, post, put, web, HttpResponse };
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{ auth::AuthUser, db, models };
+use crate::errors::AppResult;
+use crate::pagination::{ PageParams, SortOrder };
+use crate::routes::article_routes;
+use db::{ article_table_helper, reading_list_table_helper };
+use models::{ ArticleFilters, ArticleSummary, InsertReadingList, ListingInclude, Reorder, UpdateReadingList };
+
+/// `/reading-lists` and `/bookmarks` routes. Bookmarking itself lives under
+/// `/articles/{id}/bookmark` and is registered by `article_routes`.
+pub fn reading_list_scopes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/reading-lists")
+            .service(list_reading_lists_handler)
+            .service(create_reading_list_handler)
+            .service(reorder_reading_lists_handler)
+            .service(get_reading_list_handler)
+            .service(update_reading_list_handler)
+            .service(delete_reading_list_handler)
+            .service(add_list_article_handler)
+            .service(remove_list_article_handler)
+            .service(reorder_list_articles_handler)
+    );
+    cfg.service(bookmarks_handler);
+}
+
+#[put("/{id}/bookmark")]
+pub async fn add_bookmark_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>) -> AppResult<HttpResponse> {
+    let article = article_table_helper::get_article(&pool, id.into_inner(), Some(&user.id)).await?;
+    reading_list_table_helper::add_bookmark(&pool, &user.id, &article.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Article bookmarked successfully"
+    })))
+}
+
+#[delete("/{id}/bookmark")]
+pub async fn remove_bookmark_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>) -> AppResult<HttpResponse> {
+    reading_list_table_helper::remove_bookmark(&pool, &user.id, &id.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Bookmark removed successfully"
+    })))
+}
+
+#[get("/bookmarks")]
+async fn bookmarks_handler(pool: web::Data<PgPool>, user: AuthUser, page: web::Query<PageParams>, include: web::Query<ListingInclude>) -> AppResult<HttpResponse> {
+    let page = page.into_inner().into_page(20, SortOrder::Desc)?;
+    let filters = ArticleFilters {
+        bookmarked_by: Some(user.id.clone()),
+        ..Default::default()
+    };
+    let articles = article_table_helper::list_articles(&pool, &filters, Some(&user.id), &page).await?;
+
+    Ok(article_routes::articles_response(articles, include.content()?))
+}
+
+#[get("/")]
+async fn list_reading_lists_handler(pool: web::Data<PgPool>, user: AuthUser) -> AppResult<HttpResponse> {
+    let lists = reading_list_table_helper::list_reading_lists(&pool, &user.id, true).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "reading_lists": lists
+    })))
+}
+
+#[post("/")]
+async fn create_reading_list_handler(pool: web::Data<PgPool>, user: AuthUser, data: web::Json<InsertReadingList>) -> AppResult<HttpResponse> {
+    let list_id = reading_list_table_helper::insert_reading_list(&pool, &user.id, data.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Reading list created successfully",
+        "id": list_id
+    })))
+}
+
+#[put("/order")]
+async fn reorder_reading_lists_handler(pool: web::Data<PgPool>, user: AuthUser, data: web::Json<Reorder>) -> AppResult<HttpResponse> {
+    reading_list_table_helper::reorder_reading_lists(&pool, &user.id, &data.ids).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Reading lists reordered successfully"
+    })))
+}
+
+#[get("/{id}")]
+async fn get_reading_list_handler(pool: web::Data<PgPool>, user: Option<AuthUser>, id: web::Path<String>, include: web::Query<ListingInclude>) -> AppResult<HttpResponse> {
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+    let list = reading_list_table_helper::get_reading_list(&pool, &id, viewer_id).await?;
+    let articles = article_table_helper::list_reading_list_articles(&pool, &list.id, viewer_id).await?;
+
+    let articles = if include.content()? {
+        json!(articles)
+    } else {
+        json!(articles.into_iter().map(ArticleSummary::from).collect::<Vec<_>>())
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "reading_list": list,
+        "articles": articles
+    })))
+}
+
+#[put("/{id}")]
+async fn update_reading_list_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>, data: web::Json<UpdateReadingList>) -> AppResult<HttpResponse> {
+    let list = reading_list_table_helper::get_own_reading_list(&pool, &id, &user.id).await?;
+    reading_list_table_helper::update_reading_list(&pool, &list, data.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Reading list updated successfully"
+    })))
+}
+
+#[delete("/{id}")]
+async fn delete_reading_list_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>) -> AppResult<HttpResponse> {
+    let list = reading_list_table_helper::get_own_reading_list(&pool, &id, &user.id).await?;
+    reading_list_table_helper::delete_reading_list(&pool, &list.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Reading list deleted successfully"
+    })))
+}
+
+#[put("/{id}/articles/{article_id}")]
+async fn add_list_article_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<(String, String)>) -> AppResult<HttpResponse> {
+    let (id, article_id) = path.into_inner();
+    let list = reading_list_table_helper::get_own_reading_list(&pool, &id, &user.id).await?;
+    let article = article_table_helper::get_article(&pool, article_id, Some(&user.id)).await?;
+    reading_list_table_helper::add_list_article(&pool, &list, &article.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Article added to reading list"
+    })))
+}
+
+#[delete("/{id}/articles/{article_id}")]
+async fn remove_list_article_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<(String, String)>) -> AppResult<HttpResponse> {
+    let (id, article_id) = path.into_inner();
+    let list = reading_list_table_helper::get_own_reading_list(&pool, &id, &user.id).await?;
+    reading_list_table_helper::remove_list_article(&pool, &list, &article_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Article removed from reading list"
+    })))
+}
+
+#[put("/{id}/order")]
+async fn reorder_list_articles_handler(pool: web::Data<PgPool>, user: AuthUser, id: web::Path<String>, data: web::Json<Reorder>) -> AppResult<HttpResponse> {
+    let list = reading_list_table_helper::get_own_reading_list(&pool, &id, &user.id).await?;
+    reading_list_table_helper::reorder_list_articles(&pool, &list, &data.ids).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Reading list reordered successfully"
+    })))
+}
@@ -0,0 +1,64 @@
+use actix_web::{ get, web, HttpResponse };
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{ auth::AuthUser, db, models };
+use crate::errors::{ AppError, AppResult };
+use crate::pagination::{ PageParams, SortOrder, MAX_LIMIT };
+use crate::routes::article_routes;
+use db::{ article_table_helper, tag_table_helper };
+use models::{ ArticleFilters, ListingInclude, TagQuery };
+
+pub fn tag_scopes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/tags")
+            .service(popular_tags_handler)
+            .service(autocomplete_handler)
+            .service(tag_articles_handler)
+    );
+}
+
+#[get("/")]
+async fn popular_tags_handler(pool: web::Data<PgPool>, query: web::Query<TagQuery>) -> AppResult<HttpResponse> {
+    let limit = tag_limit(query.limit, 20)?;
+    let tags = tag_table_helper::popular_tags(&pool, limit).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "tags": tags
+    })))
+}
+
+#[get("/autocomplete")]
+async fn autocomplete_handler(pool: web::Data<PgPool>, query: web::Query<TagQuery>) -> AppResult<HttpResponse> {
+    let limit = tag_limit(query.limit, 10)?;
+    let prefix = query.q.as_deref().unwrap_or_default();
+    let tags = tag_table_helper::autocomplete_tags(&pool, prefix, limit).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "tags": tags
+    })))
+}
+
+#[get("/{slug}/articles")]
+async fn tag_articles_handler(pool: web::Data<PgPool>, user: Option<AuthUser>, slug: web::Path<String>, page: web::Query<PageParams>, include: web::Query<ListingInclude>) -> AppResult<HttpResponse> {
+    let page = page.into_inner().into_page(20, SortOrder::Desc)?;
+    let filters = ArticleFilters {
+        tag: Some(slug.into_inner()),
+        ..Default::default()
+    };
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+    let articles = article_table_helper::list_articles(&pool, &filters, viewer_id, &page).await?;
+
+    Ok(article_routes::articles_response(articles, include.content()?))
+}
+
+fn tag_limit(limit: Option<i64>, default: i64) -> AppResult<i64> {
+    let limit = limit.unwrap_or(default);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(AppError::Validation(format!("limit must be between 1 and {}", MAX_LIMIT)));
+    }
+
+    Ok(limit)
+}
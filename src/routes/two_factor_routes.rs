@@ -0,0 +1,52 @@
+use actix_web::{ delete, post, web, HttpResponse };
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::{ auth::AuthUser, db, models, totp };
+use crate::config::Config;
+use crate::errors::AppResult;
+use db::{ two_factor_table_helper, user_table_helper };
+use models::{ SecondFactor, TotpCode };
+
+// The routes below live under `/users` and are registered by `user_routes`.
+
+/// Starts enrollment: a new secret and its `otpauth://` URI for the
+/// authenticator app. Logins are unaffected until a code confirms it.
+#[post("/2fa/enroll")]
+pub async fn enroll_handler(pool: web::Data<PgPool>, config: web::Data<Config>, user: AuthUser) -> AppResult<HttpResponse> {
+    let account = user_table_helper::get_user_by_id(&pool, user.id).await?;
+
+    let secret = totp::generate_secret();
+    let uri = totp::otpauth_uri(&secret, &account.email)?;
+    two_factor_table_helper::set_pending_secret(&pool, &config.totp_key, &account.id, &secret).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "secret": secret,
+        "otpauth_uri": uri
+    })))
+}
+
+/// Confirms enrollment with a first code and hands out the recovery codes.
+/// They are shown only this once.
+#[post("/2fa/confirm")]
+pub async fn confirm_handler(pool: web::Data<PgPool>, config: web::Data<Config>, user: AuthUser, data: web::Json<TotpCode>) -> AppResult<HttpResponse> {
+    let recovery_codes = two_factor_table_helper::enable_two_factor(&pool, &config.totp_key, &user.id, &data.code).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Two-factor authentication enabled",
+        "recovery_codes": recovery_codes
+    })))
+}
+
+#[delete("/2fa")]
+pub async fn disable_handler(pool: web::Data<PgPool>, config: web::Data<Config>, user: AuthUser, data: web::Json<SecondFactor>) -> AppResult<HttpResponse> {
+    two_factor_table_helper::check_second_factor(&pool, &config.totp_key, &user.id, &data).await?;
+    two_factor_table_helper::disable_two_factor(&pool, &user.id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Two-factor authentication disabled"
+    })))
+}
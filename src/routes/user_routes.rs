@@ -1,116 +1,452 @@
-use actix_web::{web, get, Responder, HttpResponse, post, put};
+use actix_web::{web, delete, get, HttpResponse, post, put};
+use chrono::Duration;
 use serde_json::json;
+use sqlx::PgPool;
 
-use crate::{ db, models };
-use models::{ LoginUser, InsertUser, UpdateUser };
-use db::user_table_helper;
+use crate::{ auth::{ self, AuthUser, TokenPurpose }, db, models };
+use crate::config::Config;
+use crate::errors::{ AppError, AppResult };
+use crate::mailer::{ self, Email, Mailer };
+use crate::pagination::{ PageParams, SortOrder };
+use crate::permissions::Permission;
+use crate::routes::{ article_routes, two_factor_routes };
+use models::{
+    ArticleFilters, ChangePassword, CompleteLogin, EmailAddress, ListingInclude, LoginUser, InsertUser, RefreshToken,
+    ResetPassword, SavedUser, UpdateUser, VerifyEmail
+};
+use db::{
+    article_table_helper, follow_table_helper, reading_list_table_helper, token_table_helper,
+    two_factor_table_helper, user_table_helper
+};
 
 pub fn user_scopes(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/users")
             .service(root_handler)
             .service(new_user_handler)
+            .service(verify_email_handler)
+            .service(resend_verification_handler)
+            .service(forgot_password_handler)
+            .service(reset_password_handler)
+            .service(change_password_handler)
             .service(login_user_handler)
+            .service(complete_login_handler)
+            .service(refresh_token_handler)
+            .service(logout_handler)
             .service(update_user_handler)
+            .service(confirm_email_change_handler)
             .service(get_user_by_id_handler)
             .service(get_user_latest_articles)
+            .service(follow_handler)
+            .service(unfollow_handler)
+            .service(followers_handler)
+            .service(following_handler)
+            .service(user_reading_lists_handler)
+            .service(two_factor_routes::enroll_handler)
+            .service(two_factor_routes::confirm_handler)
+            .service(two_factor_routes::disable_handler)
     );
 }
 
 #[get("/")]
-async fn root_handler() -> impl Responder {
-    if let Ok(users) = user_table_helper::fetch_all_users().await {
-        let response = json!({
-            "status": "ok",
-            "length": users.len(),
-            "users": users
-        });
+async fn root_handler(pool: web::Data<PgPool>, page: web::Query<PageParams>) -> AppResult<HttpResponse> {
+    let page = page.into_inner().into_page(20, SortOrder::Asc)?;
+    let users = user_table_helper::list_public_users(&pool, &page).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "length": users.items.len(),
+        "users": users.items,
+        "next_cursor": users.next_cursor,
+        "prev_cursor": users.prev_cursor
+    })))
+}
 
-        return HttpResponse::Ok().json(response);
+/// Creates an account awaiting verification and emails the link. The account
+/// exists even if the email cannot be sent; `/users/verify/resend` retries.
+#[post("/signup")]
+async fn new_user_handler(pool: web::Data<PgPool>, config: web::Data<Config>, mailer: web::Data<dyn Mailer>, data: web::Json<InsertUser>) -> AppResult<HttpResponse> {
+    let user = user_table_helper::insert_user(&pool, data.into_inner(), config.bcrypt_cost).await?;
+
+    if let Err(e) = send_verification_email(&pool, &config, mailer, &user.id, &user.email).await {
+        log::error!("Failed to send verification email: {}", e);
     }
 
-    return HttpResponse::InternalServerError()
-        .json(json!({
-            "status": "failed",
-            "message": "something went wrong"
-        }));
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Check your email to verify your account",
+        "user": user,
+    })))
 }
 
-#[post("/signup")]
-async fn new_user_handler(data: web::Json<InsertUser>) -> impl Responder {
-    match user_table_helper::insert_user(data.into_inner()).await {
-        Ok(user) => {
-            HttpResponse::Ok()
-                .json(json!({
-                    "status": "ok",
-                    "user": user,
-                }))
-        },
-        Err(e) => {
-            println!("{:?}", &e);
-            HttpResponse::Conflict()
-                .json(json!({
-                    "status": "failed",
-                    "message": e.to_string()
-                }))
+#[post("/verify")]
+async fn verify_email_handler(pool: web::Data<PgPool>, config: web::Data<Config>, data: web::Json<VerifyEmail>) -> AppResult<HttpResponse> {
+    let user_id = auth::redeem_single_use_token(&pool, &config, TokenPurpose::VerifyEmail, &data.token).await?;
+    let user = user_table_helper::get_user_by_id(&pool, user_id).await?;
+
+    // Suspended accounts stay suspended.
+    if user.account_status == "pending_verification" {
+        user_table_helper::update_account_status(&pool, &user.email, "active").await?;
+    }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Email verified successfully"
+    })))
+}
+
+/// Sends a new verification email. Like `forgot_password_handler`, the work
+/// happens in the background, so neither the answer nor its timing reveals
+/// whether the address belongs to an unverified account.
+#[post("/verify/resend")]
+async fn resend_verification_handler(pool: web::Data<PgPool>, config: web::Data<Config>, mailer: web::Data<dyn Mailer>, data: web::Json<EmailAddress>) -> AppResult<HttpResponse> {
+    let email = data.into_inner().email;
+
+    actix_web::rt::spawn(async move {
+        if let Err(e) = resend_verification_email(&pool, &config, mailer, &email).await {
+            log::error!("Failed to resend verification email: {}", e);
         }
+    });
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "If the account is awaiting verification, a new email is on its way"
+    })))
+}
+
+/// Emails a password reset link. The lookup and the email happen in the
+/// background, so neither the answer nor its timing reveals whether the
+/// address has an account.
+#[post("/password/forgot")]
+async fn forgot_password_handler(pool: web::Data<PgPool>, config: web::Data<Config>, mailer: web::Data<dyn Mailer>, data: web::Json<EmailAddress>) -> AppResult<HttpResponse> {
+    let email = data.into_inner().email;
+
+    actix_web::rt::spawn(async move {
+        if let Err(e) = send_reset_email(&pool, &config, mailer, &email).await {
+            log::error!("Failed to send password reset email: {}", e);
+        }
+    });
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "If the address belongs to an account, a reset link is on its way"
+    })))
+}
+
+/// Sets a new password with an emailed reset token and logs the account out
+/// everywhere. Following the link proves the address, so an unverified
+/// account is verified as well.
+#[post("/password/reset")]
+async fn reset_password_handler(pool: web::Data<PgPool>, config: web::Data<Config>, data: web::Json<ResetPassword>) -> AppResult<HttpResponse> {
+    let data = data.into_inner();
+    // Checked first so a rejected password does not use up the token.
+    user_table_helper::validate_password(&data.password)?;
+    let user_id = auth::redeem_single_use_token(&pool, &config, TokenPurpose::ResetPassword, &data.token).await?;
+    let user = user_table_helper::get_user_by_id(&pool, user_id).await?;
+
+    user_table_helper::update_password(&pool, &user.id, data.password, config.bcrypt_cost).await?;
+    token_table_helper::revoke_user_tokens(&pool, &user.id).await?;
+
+    if user.account_status == "pending_verification" {
+        user_table_helper::update_account_status(&pool, &user.email, "active").await?;
     }
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Password reset successfully"
+    })))
+}
+
+/// Changes the caller's password. Like a reset, it logs the account out
+/// everywhere; this session carries on with the fresh tokens returned.
+#[put("/password")]
+async fn change_password_handler(pool: web::Data<PgPool>, config: web::Data<Config>, user: AuthUser, data: web::Json<ChangePassword>) -> AppResult<HttpResponse> {
+    let data = data.into_inner();
+    user_table_helper::check_password(&pool, &user.id, &data.current_password).await?;
+
+    user_table_helper::update_password(&pool, &user.id, data.password, config.bcrypt_cost).await?;
+    token_table_helper::revoke_user_tokens(&pool, &user.id).await?;
+
+    let tokens = auth::issue_tokens(&pool, &config, &user.id, None).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Password changed successfully",
+        "tokens": tokens
+    })))
 }
 
+/// Updates the caller's profile. A new email address takes effect only once
+/// the link sent to it is followed. As on signup, the update is kept when
+/// that link cannot be sent; sending the address again retries.
 #[put("/update")]
-async fn update_user_handler(data: web::Json<UpdateUser>) -> impl Responder {
-    match user_table_helper::update_user(data.into_inner()).await {
-        Ok(_) => HttpResponse::Ok().json(json!({
+async fn update_user_handler(pool: web::Data<PgPool>, config: web::Data<Config>, mailer: web::Data<dyn Mailer>, user: AuthUser, data: web::Json<UpdateUser>) -> AppResult<HttpResponse> {
+    let data = data.into_inner();
+    let new_email = data.email.clone();
+    user_table_helper::update_user(&pool, user.id.clone(), data).await?;
+
+    let message = match new_email {
+        Some(email) => match send_email_change_email(&pool, &config, mailer, &user.id, &email).await {
+            Ok(()) => "User updated successfully; check the new email address to confirm it",
+            Err(e) => {
+                log::error!("Failed to send email change confirmation: {}", e);
+                "User updated successfully, but the confirmation email could not be sent; send the new address again to retry"
+            },
+        },
+        None => "User updated successfully",
+    };
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": message
+    })))
+}
+
+#[post("/email/verify")]
+async fn confirm_email_change_handler(pool: web::Data<PgPool>, config: web::Data<Config>, data: web::Json<VerifyEmail>) -> AppResult<HttpResponse> {
+    let user_id = auth::redeem_single_use_token(&pool, &config, TokenPurpose::ChangeEmail, &data.token).await?;
+    let email = user_table_helper::apply_pending_email(&pool, &user_id).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "Email address changed successfully",
+        "email": email
+    })))
+}
+
+/// Logs in with email and password. Accounts with two-factor
+/// authentication get a short-lived `challenge` instead of tokens, to be
+/// completed at `/users/login/2fa`.
+#[post("/login")]
+async fn login_user_handler(pool: web::Data<PgPool>, config: web::Data<Config>, data: web::Json<LoginUser>) -> AppResult<HttpResponse> {
+    let user = user_table_helper::get_user_info_by_credentials(&pool, data.into_inner()).await?;
+
+    if two_factor_table_helper::get_two_factor(&pool, &user.id).await?.enabled {
+        let ttl = Duration::minutes(auth::LOGIN_CHALLENGE_TTL_MINUTES);
+        let challenge = auth::issue_single_use_token(&pool, &config, &user.id, TokenPurpose::LoginChallenge, ttl).await?;
+
+        return Ok(HttpResponse::Ok().json(json!({
             "status": "ok",
-            "message": "User updated successfully"
-        })),
-        Err(e) => {
-            HttpResponse::Conflict()
-                .json(json!({
-                    "status": "failed",
-                    "message": e.to_string()
-                }))
-        }
+            "two_factor_required": true,
+            "challenge": challenge,
+            "expires_in": ttl.num_seconds()
+        })));
     }
+
+    user_table_helper::update_last_login_date(&pool, &user.email).await?;
+
+    let tokens = auth::issue_tokens(&pool, &config, &user.id, None).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "user": user,
+        "tokens": tokens
+    })))
 }
 
-#[post("/login")]
-async fn login_user_handler(data: web::Json<LoginUser>) -> impl Responder {
-    match user_table_helper::get_user_info_by_credentials(data.into_inner()).await {
-        Some(user) => {
-            user_table_helper::update_last_login_date(&user.email)
-                .await
-                .unwrap();
-            HttpResponse::Ok()
-                .json(json!({
-                    "status": "ok",
-                    "user": user
-                }))
-        },
-        None => {
-            HttpResponse::BadRequest()
-                .json(json!({
-                    "status": "failed",
-                    "message": "Invalid Credentials"
-                }))
-        }
+/// Completes a login challenge with a TOTP or recovery code. A challenge
+/// is good for one attempt; after a wrong code the login starts over.
+#[post("/login/2fa")]
+async fn complete_login_handler(pool: web::Data<PgPool>, config: web::Data<Config>, data: web::Json<CompleteLogin>) -> AppResult<HttpResponse> {
+    let user_id = auth::redeem_single_use_token(&pool, &config, TokenPurpose::LoginChallenge, &data.challenge).await?;
+    two_factor_table_helper::check_second_factor(&pool, &config.totp_key, &user_id, &data.factor).await?;
+
+    let user = user_table_helper::get_user_by_id(&pool, user_id).await?;
+    if user.account_status != "active" {
+        return Err(AppError::Forbidden("Account is not active".to_string()));
     }
+    user_table_helper::update_last_login_date(&pool, &user.email).await?;
+
+    let tokens = auth::issue_tokens(&pool, &config, &user.id, None).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "user": SavedUser {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            last_login_date: user.last_login_date,
+        },
+        "tokens": tokens
+    })))
 }
 
-#[get("/{user_id}")]
-async fn get_user_by_id_handler(path: web::Path<String>) -> impl Responder {
-    let user = user_table_helper::get_user_by_id(path.into_inner()).await;
-    HttpResponse::Ok().json(json!({
+#[post("/token/refresh")]
+async fn refresh_token_handler(pool: web::Data<PgPool>, config: web::Data<Config>, data: web::Json<RefreshToken>) -> AppResult<HttpResponse> {
+    let (user_id, family_id) = token_table_helper::consume_refresh_token(&pool, &data.refresh_token).await?;
+    let tokens = auth::issue_tokens(&pool, &config, &user_id, Some(family_id)).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "tokens": tokens
+    })))
+}
+
+#[post("/logout")]
+async fn logout_handler(pool: web::Data<PgPool>, _user: AuthUser, data: web::Json<RefreshToken>) -> AppResult<HttpResponse> {
+    token_table_helper::revoke_refresh_token(&pool, &data.refresh_token).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
         "status": "ok",
-        "user": user
-    }))
+        "message": "Logged out successfully"
+    })))
+}
+
+#[get("/{user_id}")]
+async fn get_user_by_id_handler(pool: web::Data<PgPool>, viewer: Option<AuthUser>, path: web::Path<String>) -> AppResult<HttpResponse> {
+    let user_id = path.into_inner();
+    let can_view_account = match &viewer {
+        Some(viewer) if viewer.id == user_id => true,
+        Some(viewer) => viewer.role.can(Permission::ManageUsers),
+        None => false,
+    };
+
+    let response = if can_view_account {
+        json!({
+            "status": "ok",
+            "user": user_table_helper::get_user_by_id(&pool, user_id).await?
+        })
+    } else {
+        json!({
+            "status": "ok",
+            "user": user_table_helper::get_public_user(&pool, user_id).await?
+        })
+    };
+
+    Ok(HttpResponse::Ok().json(response))
 }
 
 #[get("/{user_id}/latest")]
-async fn get_user_latest_articles(path: web::Path<String>) -> impl Responder {
-    let articles = user_table_helper::get_latest_articles_by_user_id(path.into_inner()).await;
-    HttpResponse::Ok().json(json!({
+async fn get_user_latest_articles(pool: web::Data<PgPool>, user: Option<AuthUser>, path: web::Path<String>, page: web::Query<PageParams>, include: web::Query<ListingInclude>) -> AppResult<HttpResponse> {
+    let page = page.into_inner().into_page(7, SortOrder::Desc)?;
+    let filters = ArticleFilters {
+        author_id: Some(path.into_inner()),
+        ..Default::default()
+    };
+    let viewer_id = user.as_ref().map(|user| user.id.as_str());
+    let articles = article_table_helper::list_articles(&pool, &filters, viewer_id, &page).await?;
+
+    Ok(article_routes::articles_response(articles, include.content()?))
+}
+
+#[put("/{user_id}/follow")]
+async fn follow_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> AppResult<HttpResponse> {
+    follow_table_helper::follow(&pool, &user.id, &path.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "User followed successfully"
+    })))
+}
+
+#[delete("/{user_id}/follow")]
+async fn unfollow_handler(pool: web::Data<PgPool>, user: AuthUser, path: web::Path<String>) -> AppResult<HttpResponse> {
+    follow_table_helper::unfollow(&pool, &user.id, &path.into_inner()).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "message": "User unfollowed successfully"
+    })))
+}
+
+#[get("/{user_id}/followers")]
+async fn followers_handler(pool: web::Data<PgPool>, path: web::Path<String>, page: web::Query<PageParams>) -> AppResult<HttpResponse> {
+    let user = user_table_helper::get_public_user(&pool, path.into_inner()).await?;
+    let page = page.into_inner().into_page(20, SortOrder::Desc)?;
+    let followers = follow_table_helper::list_followers(&pool, &user.id, &page).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "users": followers.items,
+        "next_cursor": followers.next_cursor,
+        "prev_cursor": followers.prev_cursor
+    })))
+}
+
+#[get("/{user_id}/following")]
+async fn following_handler(pool: web::Data<PgPool>, path: web::Path<String>, page: web::Query<PageParams>) -> AppResult<HttpResponse> {
+    let user = user_table_helper::get_public_user(&pool, path.into_inner()).await?;
+    let page = page.into_inner().into_page(20, SortOrder::Desc)?;
+    let following = follow_table_helper::list_following(&pool, &user.id, &page).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "ok",
+        "users": following.items,
+        "next_cursor": following.next_cursor,
+        "prev_cursor": following.prev_cursor
+    })))
+}
+
+/// A user's public reading lists, or all of them for the user themselves.
+#[get("/{user_id}/reading-lists")]
+async fn user_reading_lists_handler(pool: web::Data<PgPool>, viewer: Option<AuthUser>, path: web::Path<String>) -> AppResult<HttpResponse> {
+    let user = user_table_helper::get_public_user(&pool, path.into_inner()).await?;
+    let is_owner = viewer.is_some_and(|viewer| viewer.id == user.id);
+    let lists = reading_list_table_helper::list_reading_lists(&pool, &user.id, is_owner).await?;
+
+    Ok(HttpResponse::Ok().json(json!({
         "status": "ok",
-        "articles": articles
-    }))
+        "reading_lists": lists
+    })))
+}
+
+async fn send_verification_email(pool: &PgPool, config: &Config, mailer: web::Data<dyn Mailer>, user_id: &str, email: &str) -> AppResult<()> {
+    let ttl = Duration::hours(auth::VERIFICATION_TOKEN_TTL_HOURS);
+    let token = auth::issue_single_use_token(pool, config, user_id, TokenPurpose::VerifyEmail, ttl).await?;
+
+    mailer::deliver(mailer, Email {
+        to: email.to_string(),
+        subject: "Verify your Inklink email address".to_string(),
+        body: format!(
+            "Welcome to Inklink!\n\n\
+            Confirm your email address by opening this link within {} hours:\n\n\
+            {}/verify-email?token={}\n\n\
+            If you did not sign up, you can ignore this email.\n",
+            auth::VERIFICATION_TOKEN_TTL_HOURS, config.app_url, token),
+    }).await
+}
+
+async fn send_email_change_email(pool: &PgPool, config: &Config, mailer: web::Data<dyn Mailer>, user_id: &str, email: &str) -> AppResult<()> {
+    let ttl = Duration::hours(auth::VERIFICATION_TOKEN_TTL_HOURS);
+    let token = auth::issue_single_use_token(pool, config, user_id, TokenPurpose::ChangeEmail, ttl).await?;
+
+    mailer::deliver(mailer, Email {
+        to: email.to_string(),
+        subject: "Confirm your new Inklink email address".to_string(),
+        body: format!(
+            "Someone asked to use this address for an Inklink account.\n\n\
+            Confirm the change by opening this link within {} hours:\n\n\
+            {}/confirm-email?token={}\n\n\
+            Until then the account keeps its current address. If it was not you, you can ignore this email.\n",
+            auth::VERIFICATION_TOKEN_TTL_HOURS, config.app_url, token),
+    }).await
+}
+
+async fn resend_verification_email(pool: &PgPool, config: &Config, mailer: web::Data<dyn Mailer>, email: &str) -> AppResult<()> {
+    match user_table_helper::find_user_by_email(pool, email).await? {
+        Some(user) if user.account_status == "pending_verification" =>
+            send_verification_email(pool, config, mailer, &user.id, &user.email).await,
+        _ => Ok(()),
+    }
+}
+
+async fn send_reset_email(pool: &PgPool, config: &Config, mailer: web::Data<dyn Mailer>, email: &str) -> AppResult<()> {
+    let user = match user_table_helper::find_user_by_email(pool, email).await? {
+        Some(user) if user.account_status != "suspended" => user,
+        _ => return Ok(()),
+    };
+
+    let ttl = Duration::minutes(auth::RESET_TOKEN_TTL_MINUTES);
+    let token = auth::issue_single_use_token(pool, config, &user.id, TokenPurpose::ResetPassword, ttl).await?;
+
+    mailer::deliver(mailer, Email {
+        to: user.email,
+        subject: "Reset your Inklink password".to_string(),
+        body: format!(
+            "Someone asked to reset the password of your Inklink account.\n\n\
+            Choose a new password by opening this link within {} minutes:\n\n\
+            {}/reset-password?token={}\n\n\
+            If it was not you, you can ignore this email; your password stays the same.\n",
+            auth::RESET_TOKEN_TTL_MINUTES, config.app_url, token),
+    }).await
 }
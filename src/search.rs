@@ -0,0 +1,209 @@
+use crate::errors::{ AppError, AppResult };
+
+/// Wrapped around matches by `ts_headline`. Article text never contains
+/// them, see `markdown::render`, so `highlight` can tell them apart from
+/// the text.
+pub const HIGHLIGHT_START: char = '\u{2}';
+pub const HIGHLIGHT_STOP: char = '\u{3}';
+
+/// `ts_headline` options for search snippets.
+pub fn headline_options() -> String {
+    format!(
+        "StartSel={}, StopSel={}, MaxFragments=2, MaxWords=30, MinWords=10",
+        HIGHLIGHT_START, HIGHLIGHT_STOP)
+}
+
+pub fn is_highlight_marker(c: char) -> bool {
+    c == HIGHLIGHT_START || c == HIGHLIGHT_STOP
+}
+
+/// Turns a plain-text snippet from `ts_headline` into safe HTML: the text
+/// is escaped and only the highlight markers become `<mark>` tags.
+pub fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    let mut open = false;
+
+    for c in snippet.chars() {
+        match c {
+            HIGHLIGHT_START if !open => {
+                html.push_str("<mark>");
+                open = true;
+            },
+            HIGHLIGHT_STOP if open => {
+                html.push_str("</mark>");
+                open = false;
+            },
+            HIGHLIGHT_START | HIGHLIGHT_STOP => {},
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+
+    if open {
+        html.push_str("</mark>");
+    }
+
+    html
+}
+
+/// Turns a search box query into `to_tsquery` syntax. Words are required,
+/// `"quoted words"` must appear as a phrase, `word*` matches prefixes,
+/// `-word` excludes and `OR` between two terms accepts either. Anything
+/// that is not a letter or digit is dropped, so user input can never break
+/// out of the query syntax.
+pub fn to_tsquery(input: &str) -> AppResult<String> {
+    let mut query = String::new();
+    let mut operator = " & ";
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let term = if c == '"' {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|c| *c != '"').collect();
+            phrase_of(&phrase, false)
+        } else {
+            let token: String = std::iter::from_fn(|| chars.next_if(|c| !c.is_whitespace())).collect();
+
+            if token == "OR" {
+                operator = " | ";
+                continue;
+            }
+
+            let (negated, token) = match token.strip_prefix('-') {
+                Some(rest) => (true, rest),
+                None => (false, token.as_str()),
+            };
+            let (prefix, token) = match token.strip_suffix('*') {
+                Some(rest) => (true, rest),
+                None => (false, token),
+            };
+
+            phrase_of(token, prefix).map(|term| if negated { format!("!{}", term) } else { term })
+        };
+
+        if let Some(term) = term {
+            if !query.is_empty() {
+                query.push_str(operator);
+            }
+            query.push_str(&term);
+        }
+
+        operator = " & ";
+    }
+
+    if query.is_empty() {
+        return Err(AppError::Validation("q must contain at least one word".to_string()));
+    }
+
+    Ok(query)
+}
+
+/// Joins the words of `text` into a phrase, marking the last word as a
+/// prefix when asked to.
+fn phrase_of(text: &str, prefix: bool) -> Option<String> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+
+    let mut phrase = words.join(" <-> ");
+    if phrase.is_empty() {
+        return None;
+    }
+
+    if prefix {
+        phrase.push_str(":*");
+    }
+
+    Some(if words.len() > 1 { format!("({})", phrase) } else { phrase })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(input: &str) -> String {
+        to_tsquery(input).unwrap()
+    }
+
+    #[test]
+    fn words_are_all_required() {
+        assert_eq!(query("Rust  async"), "rust & async");
+    }
+
+    #[test]
+    fn quoted_words_are_a_phrase() {
+        assert_eq!(query("\"hello world\" rust"), "(hello <-> world) & rust");
+        assert_eq!(query("\"unterminated phrase"), "(unterminated <-> phrase)");
+    }
+
+    #[test]
+    fn trailing_star_matches_prefixes() {
+        assert_eq!(query("rus*"), "rus:*");
+        assert_eq!(query("foo-bar*"), "(foo <-> bar:*)");
+    }
+
+    #[test]
+    fn minus_excludes() {
+        assert_eq!(query("rust -java"), "rust & !java");
+        assert_eq!(query("-foo-bar"), "!(foo <-> bar)");
+    }
+
+    #[test]
+    fn or_joins_two_terms() {
+        assert_eq!(query("rust OR go web"), "rust | go & web");
+        assert_eq!(query("OR rust"), "rust");
+        assert_eq!(query("rust or go"), "rust & or & go");
+    }
+
+    #[test]
+    fn query_syntax_is_escaped() {
+        assert_eq!(query("a' & b | !c"), "a & b & c");
+        assert_eq!(query("foo:*bar (x)"), "(foo <-> bar) & x");
+        assert_eq!(query("\"a' <-> b\""), "(a <-> b)");
+    }
+
+    #[test]
+    fn queries_without_words_are_rejected() {
+        for input in ["", "   ", "!!! ***", "\"\"", "OR"] {
+            assert!(matches!(to_tsquery(input), Err(AppError::Validation(_))), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn highlight_marks_matches() {
+        assert_eq!(highlight("a \u{2}b\u{3} c"), "a <mark>b</mark> c");
+    }
+
+    #[test]
+    fn highlight_escapes_text() {
+        assert_eq!(
+            highlight("<img src=x onerror=\"alert('x')\"> & \u{2}b\u{3}"),
+            "&lt;img src=x onerror=&quot;alert(&#39;x&#39;)&quot;&gt; &amp; <mark>b</mark>");
+    }
+
+    #[test]
+    fn highlight_keeps_marks_balanced() {
+        assert_eq!(highlight("\u{2}open"), "<mark>open</mark>");
+        assert_eq!(highlight("stray\u{3} stop"), "stray stop");
+        assert_eq!(highlight("\u{2}a\u{2}b\u{3}"), "<mark>ab</mark>");
+    }
+
+    #[test]
+    fn headline_options_use_the_markers() {
+        let options = headline_options();
+
+        assert!(options.contains(&format!("StartSel={}", HIGHLIGHT_START)));
+        assert!(options.contains(&format!("StopSel={}", HIGHLIGHT_STOP)));
+    }
+}
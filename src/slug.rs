@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+
+use deunicode::deunicode;
+
+/// Longest slug derived from an article title, before any suffix.
+const MAX_ARTICLE_SLUG_LENGTH: usize = 80;
+
+/// Transliterates `text` to ASCII, lowercases it and joins its runs of
+/// letters and digits with dashes, e.g. `"Crème brûlée & Rust!"` becomes
+/// `"creme-brulee-rust"`.
+pub fn slugify(text: &str) -> String {
+    deunicode(text)
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_ascii_lowercase())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Slug for an article title, cut at a word boundary so permalinks stay
+/// short. Titles without any letters or digits become `article`.
+pub fn article_slug(title: &str) -> String {
+    let mut slug = slugify(title);
+
+    if slug.len() > MAX_ARTICLE_SLUG_LENGTH {
+        let cut = slug[..=MAX_ARTICLE_SLUG_LENGTH].rfind('-').unwrap_or(MAX_ARTICLE_SLUG_LENGTH);
+        slug.truncate(cut);
+    }
+
+    if slug.is_empty() {
+        slug.push_str("article");
+    }
+
+    slug
+}
+
+/// `base` if it is not `taken`, otherwise the first of `base-2`, `base-3`,
+/// ... that is not.
+pub fn first_free(base: String, taken: &HashSet<String>) -> String {
+    if !taken.contains(&base) {
+        return base;
+    }
+
+    (2..)
+        .map(|n| format!("{}-{}", base, n))
+        .find(|candidate| !taken.contains(candidate))
+        .expect("a free suffix always exists")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn taken(slugs: &[&str]) -> HashSet<String> {
+        slugs.iter().map(|slug| slug.to_string()).collect()
+    }
+
+    #[test]
+    fn slugify_transliterates_to_ascii() {
+        assert_eq!(slugify("Crème brûlée & Rust!"), "creme-brulee-rust");
+        assert_eq!(slugify("Straße"), "strasse");
+        assert_eq!(slugify("Привет мир"), "privet-mir");
+        assert_eq!(slugify("東京"), "dong-jing");
+    }
+
+    #[test]
+    fn slugify_collapses_separators() {
+        assert_eq!(slugify("  --Hello,   World--  "), "hello-world");
+        assert_eq!(slugify("C++ & C# in 2024"), "c-c-in-2024");
+        assert_eq!(slugify("!!!"), "");
+    }
+
+    #[test]
+    fn article_slug_falls_back_for_titles_without_words() {
+        assert_eq!(article_slug("???"), "article");
+        assert_eq!(article_slug(""), "article");
+    }
+
+    #[test]
+    fn long_article_slugs_are_cut_at_a_word_boundary() {
+        let title = "word ".repeat(30);
+        let slug = article_slug(&title);
+
+        assert!(slug.len() <= MAX_ARTICLE_SLUG_LENGTH);
+        assert!(slug.split('-').all(|word| word == "word"), "{}", slug);
+    }
+
+    #[test]
+    fn long_single_word_slugs_are_cut_at_the_limit() {
+        assert_eq!(article_slug(&"a".repeat(200)).len(), MAX_ARTICLE_SLUG_LENGTH);
+    }
+
+    #[test]
+    fn free_slug_is_kept() {
+        assert_eq!(first_free("rust".to_string(), &taken(&["rust-2"])), "rust");
+    }
+
+    #[test]
+    fn taken_slug_gets_the_first_free_suffix() {
+        assert_eq!(first_free("rust".to_string(), &taken(&["rust"])), "rust-2");
+        assert_eq!(first_free("rust".to_string(), &taken(&["rust", "rust-2", "rust-4"])), "rust-3");
+    }
+}
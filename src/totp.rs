@@ -0,0 +1,216 @@
+use aes_gcm::{ Aes256Gcm, KeyInit };
+use aes_gcm::aead::{ Aead, AeadCore, Payload };
+use rand::Rng;
+use rand::rngs::OsRng;
+use totp_rs::{ Algorithm, Secret, TOTP };
+
+use crate::errors::{ AppError, AppResult };
+
+const ISSUER: &str = "Inklink";
+
+/// Seconds each code is valid for.
+const STEP: u64 = 30;
+
+/// Codes from this many steps before or after the current one are still
+/// accepted, to allow for clock drift.
+const SKEW: u64 = 1;
+
+/// Recovery codes handed out when two-factor authentication is enabled.
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Recovery codes are this many characters of this alphabet, five random
+/// bits each, so 80 bits per code.
+const RECOVERY_CODE_LENGTH: usize = 16;
+const RECOVERY_CODE_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+/// Version of the stored secret format, so the cipher can change without
+/// breaking secrets already stored.
+const ENCRYPTED_SECRET_PREFIX: &str = "v1:";
+
+/// Bytes of the random nonce stored in front of each encrypted secret.
+const NONCE_LENGTH: usize = 12;
+
+/// A new random secret, base32 encoded.
+pub fn generate_secret() -> String {
+    Secret::generate_secret().to_encoded().to_string()
+}
+
+/// The `otpauth://` URI authenticator apps enroll from, usually shown as a
+/// QR code.
+pub fn otpauth_uri(secret: &str, account: &str) -> AppResult<String> {
+    Ok(totp(secret, account)?.get_url())
+}
+
+/// Finds the time step, counted from the Unix epoch, whose code is `code`.
+/// Callers store the step so the same code cannot be used twice.
+pub fn matching_step(secret: &str, code: &str, now: u64) -> AppResult<Option<i64>> {
+    // The account name only matters for the URI.
+    let totp = totp(secret, "")?;
+    let code = code.trim();
+    let current = now / STEP;
+
+    let step = (current.saturating_sub(SKEW)..=current + SKEW)
+        .find(|step| totp.check(code, step * STEP));
+
+    Ok(step.map(|step| step as i64))
+}
+
+/// Random single-use codes that stand in for a TOTP code when the
+/// authenticator is lost, drawn from the operating system's CSPRNG and
+/// written in groups of four, like `abcd-efgh-ijkl-mnop`.
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let characters: Vec<u8> = (0..RECOVERY_CODE_LENGTH)
+                .map(|_| RECOVERY_CODE_ALPHABET[OsRng.gen_range(0..RECOVERY_CODE_ALPHABET.len())])
+                .collect();
+
+            characters
+                .chunks(4)
+                .map(|group| String::from_utf8_lossy(group).into_owned())
+                .collect::<Vec<_>>()
+                .join("-")
+        })
+        .collect()
+}
+
+/// Recovery codes are compared case-insensitively and without surrounding
+/// whitespace.
+pub fn normalize_recovery_code(code: &str) -> String {
+    code.trim().to_ascii_lowercase()
+}
+
+/// Encrypts a secret for storage with AES-256-GCM under `key`, the hex
+/// encoded `totp_key` setting. The user id is bound to the ciphertext, so
+/// a secret copied to another account does not decrypt.
+pub fn encrypt_secret(key: &str, user_id: &str, secret: &str) -> AppResult<String> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher(key)?
+        .encrypt(&nonce, Payload { msg: secret.as_bytes(), aad: user_id.as_bytes() })
+        .map_err(|_| AppError::Internal("Failed to encrypt TOTP secret".to_string()))?;
+
+    Ok(format!("{}{}{}", ENCRYPTED_SECRET_PREFIX, hex::encode(nonce), hex::encode(ciphertext)))
+}
+
+/// Reverses `encrypt_secret`.
+pub fn decrypt_secret(key: &str, user_id: &str, stored: &str) -> AppResult<String> {
+    let invalid = || AppError::Internal("Cannot decrypt TOTP secret".to_string());
+
+    let encrypted = stored
+        .strip_prefix(ENCRYPTED_SECRET_PREFIX)
+        .and_then(|encrypted| hex::decode(encrypted).ok())
+        .filter(|encrypted| encrypted.len() > NONCE_LENGTH)
+        .ok_or_else(invalid)?;
+    let (nonce, ciphertext) = encrypted.split_at(NONCE_LENGTH);
+
+    let secret = cipher(key)?
+        .decrypt(nonce.into(), Payload { msg: ciphertext, aad: user_id.as_bytes() })
+        .map_err(|_| invalid())?;
+
+    String::from_utf8(secret).map_err(|_| invalid())
+}
+
+fn cipher(key: &str) -> AppResult<Aes256Gcm> {
+    hex::decode(key)
+        .ok()
+        .and_then(|key| Aes256Gcm::new_from_slice(&key).ok())
+        .ok_or_else(|| AppError::Internal("Invalid TOTP encryption key".to_string()))
+}
+
+fn totp(secret: &str, account: &str) -> AppResult<TOTP> {
+    let secret = Secret::Encoded(secret.to_string())
+        .to_bytes()
+        .map_err(|_| AppError::Internal("Invalid TOTP secret".to_string()))?;
+
+    TOTP::new(Algorithm::SHA1, 6, 0, STEP, secret, Some(ISSUER.to_string()), account.to_string())
+        .map_err(|e| AppError::Internal(format!("Invalid TOTP parameters: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "JBSWY3DPEHPK3PXPJBSWY3DPEHPK3PXP";
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const NOW: u64 = 1_700_000_010;
+
+    fn code_at(time: u64) -> String {
+        totp(SECRET, "").unwrap().generate(time)
+    }
+
+    #[test]
+    fn current_code_matches_the_current_step() {
+        let step = (NOW / STEP) as i64;
+
+        assert_eq!(matching_step(SECRET, &code_at(NOW), NOW).unwrap(), Some(step));
+        assert_eq!(matching_step(SECRET, &format!(" {} ", code_at(NOW)), NOW).unwrap(), Some(step));
+    }
+
+    #[test]
+    fn replayed_code_reports_the_step_it_was_first_accepted_at() {
+        // A code replayed within the skew window still maps to its original
+        // step, which the caller already recorded and so rejects.
+        let step = matching_step(SECRET, &code_at(NOW), NOW).unwrap();
+        let replayed = matching_step(SECRET, &code_at(NOW), NOW + STEP).unwrap();
+
+        assert_eq!(replayed, step);
+    }
+
+    #[test]
+    fn codes_within_the_skew_are_accepted() {
+        let step = (NOW / STEP) as i64;
+
+        assert_eq!(matching_step(SECRET, &code_at(NOW - STEP), NOW).unwrap(), Some(step - 1));
+        assert_eq!(matching_step(SECRET, &code_at(NOW + STEP), NOW).unwrap(), Some(step + 1));
+    }
+
+    #[test]
+    fn codes_outside_the_skew_are_rejected() {
+        assert_eq!(matching_step(SECRET, &code_at(NOW - 2 * STEP), NOW).unwrap(), None);
+        assert_eq!(matching_step(SECRET, &code_at(NOW + 2 * STEP), NOW).unwrap(), None);
+        assert_eq!(matching_step(SECRET, "not a code", NOW).unwrap(), None);
+    }
+
+    #[test]
+    fn recovery_codes_are_groups_of_random_characters() {
+        let codes = generate_recovery_codes();
+
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        for code in &codes {
+            let groups: Vec<&str> = code.split('-').collect();
+            assert_eq!(groups.len(), RECOVERY_CODE_LENGTH / 4, "{}", code);
+            assert!(groups.iter().all(|group| group.len() == 4), "{}", code);
+            assert!(code.bytes().all(|c| c == b'-' || RECOVERY_CODE_ALPHABET.contains(&c)), "{}", code);
+            assert_eq!(&normalize_recovery_code(code), code);
+        }
+
+        let unique: std::collections::HashSet<&String> = codes.iter().collect();
+        assert_eq!(unique.len(), codes.len());
+    }
+
+    #[test]
+    fn recovery_codes_are_normalized() {
+        assert_eq!(normalize_recovery_code("  ABCD-efgh-IJKL-mnop\n"), "abcd-efgh-ijkl-mnop");
+    }
+
+    #[test]
+    fn secrets_round_trip_through_encryption() {
+        let stored = encrypt_secret(KEY, "user", SECRET).unwrap();
+
+        assert!(stored.starts_with(ENCRYPTED_SECRET_PREFIX));
+        assert!(!stored.contains(SECRET));
+        assert_ne!(stored, encrypt_secret(KEY, "user", SECRET).unwrap());
+        assert_eq!(decrypt_secret(KEY, "user", &stored).unwrap(), SECRET);
+    }
+
+    #[test]
+    fn secrets_only_decrypt_for_their_user_and_key() {
+        let stored = encrypt_secret(KEY, "user", SECRET).unwrap();
+        let other_key = "ff".repeat(32);
+
+        assert!(decrypt_secret(KEY, "other", &stored).is_err());
+        assert!(decrypt_secret(&other_key, "user", &stored).is_err());
+        assert!(decrypt_secret(KEY, "user", SECRET).is_err());
+        assert!(decrypt_secret(KEY, "user", "v1:00").is_err());
+    }
+}